@@ -0,0 +1,34 @@
+#![no_main]
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use libfuzzer_sys::fuzz_target;
+use lunabank::{
+    instruction::LunabankInstruction,
+    state::{Key, LunaAsset, LunaDeposit, LunaRegistryEntry, Lunabank},
+};
+use solana_program::program_pack::Pack;
+
+fuzz_target!(|data: &[u8]| {
+    // the encodings are canonical, whatever decodes encodes back to the same bytes
+    if let Ok(instruction) = LunabankInstruction::try_from_slice(data) {
+        assert_eq!(instruction.try_to_vec().unwrap(), data);
+    }
+    // V1 accounts decode with defaults for the fields they lack, their own fields come first
+    if let Ok(bank) = Lunabank::unpack_from_slice(data) {
+        assert_eq!(&bank.try_to_vec().unwrap()[..bank.data_len()], data);
+    }
+    if let Ok(deposit) = LunaDeposit::unpack_from_slice(data) {
+        // the reserved space of V2 deposits is dropped
+        let len = match deposit.key {
+            Key::LunaDepositV2 => LunaDeposit::BANK_OFFSET,
+            _ => deposit.data_len(),
+        };
+        assert_eq!(&deposit.try_to_vec().unwrap()[..len], &data[..len]);
+    }
+    if let Ok(asset) = LunaAsset::unpack_from_slice(data) {
+        assert_eq!(asset.try_to_vec().unwrap(), data);
+    }
+    if let Ok(entry) = LunaRegistryEntry::unpack_from_slice(data) {
+        assert_eq!(entry.try_to_vec().unwrap(), data);
+    }
+});
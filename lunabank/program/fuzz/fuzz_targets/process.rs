@@ -0,0 +1,140 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use lunabank::processor::Processor;
+use solana_program::{
+    clock::Clock,
+    entrypoint::{deserialize, MAX_PERMITTED_DATA_INCREASE, NON_DUP_MARKER},
+    program_stubs::{set_syscall_stubs, SyscallStubs},
+    pubkey::Pubkey,
+    rent::Rent,
+    system_program,
+    sysvar::{self, Sysvar},
+};
+use std::sync::Once;
+
+const PROGRAM_ID: Pubkey = Pubkey::new_from_array([7; 32]);
+
+#[derive(Debug, Arbitrary)]
+struct FuzzAccount {
+    key: u8,
+    owner: u8,
+    is_signer: bool,
+    is_writable: bool,
+    lamports: u64,
+    data: Vec<u8>,
+}
+
+#[derive(Debug, Arbitrary)]
+struct FuzzInput {
+    accounts: Vec<FuzzAccount>,
+    instruction_data: Vec<u8>,
+}
+
+/// Keeps `msg!` and events quiet, CPIs are accepted as no-ops by the default stubs
+struct QuietStubs;
+
+impl SyscallStubs for QuietStubs {
+    fn sol_log(&self, _message: &str) {}
+    fn sol_log_data(&self, _fields: &[&[u8]]) {}
+}
+
+static STUBS: Once = Once::new();
+
+/// Accounts are picked from a small pool so duplicates and the expected
+/// program/sysvar addresses come up often
+fn key(index: u8) -> Pubkey {
+    match index % 9 {
+        0 => PROGRAM_ID,
+        1 => spl_token::id(),
+        2 => system_program::id(),
+        3 => sysvar::rent::id(),
+        4 => sysvar::clock::id(),
+        5 => spl_token_2022::id(),
+        n => Pubkey::new_from_array([n; 32]),
+    }
+}
+
+fn owner(index: u8) -> Pubkey {
+    match index % 4 {
+        0 => PROGRAM_ID,
+        1 => spl_token::id(),
+        2 => spl_token_2022::id(),
+        _ => system_program::id(),
+    }
+}
+
+/// Lays the input out the way the runtime serializes it for a program, so the
+/// `AccountInfo`s from `deserialize` behave as on chain, `realloc` included
+fn serialize(input: &FuzzInput) -> Vec<u64> {
+    let mut buffer = vec![];
+    buffer.extend_from_slice(&(input.accounts.len() as u64).to_le_bytes());
+    let mut keys = vec![];
+    for account in &input.accounts {
+        let key = key(account.key);
+        if let Some(position) = keys.iter().position(|k| *k == key) {
+            buffer.push(position as u8);
+            buffer.extend_from_slice(&[0; 7]);
+            keys.push(key);
+            continue;
+        }
+        keys.push(key);
+
+        // the runtime guarantees sysvar contents, only their size matters here
+        let (owner, data) = if key == sysvar::rent::id() {
+            (sysvar::id(), vec![0; Rent::size_of()])
+        } else if key == sysvar::clock::id() {
+            (sysvar::id(), vec![0; Clock::size_of()])
+        } else {
+            (owner(account.owner), account.data.clone())
+        };
+
+        buffer.push(NON_DUP_MARKER);
+        buffer.push(account.is_signer as u8);
+        buffer.push(account.is_writable as u8);
+        buffer.push(0); // executable
+        buffer.extend_from_slice(&[0; 4]); // original data len, set by `deserialize`
+        buffer.extend_from_slice(key.as_ref());
+        buffer.extend_from_slice(owner.as_ref());
+        buffer.extend_from_slice(&account.lamports.to_le_bytes());
+        buffer.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        buffer.extend_from_slice(&data);
+        buffer.resize(buffer.len() + MAX_PERMITTED_DATA_INCREASE, 0);
+        buffer.resize((buffer.len() + 7) / 8 * 8, 0);
+        buffer.extend_from_slice(&0u64.to_le_bytes()); // rent epoch
+    }
+    buffer.extend_from_slice(&(input.instruction_data.len() as u64).to_le_bytes());
+    buffer.extend_from_slice(&input.instruction_data);
+    buffer.extend_from_slice(PROGRAM_ID.as_ref());
+
+    let mut aligned = vec![0u64; (buffer.len() + 7) / 8];
+    unsafe {
+        std::ptr::copy_nonoverlapping(
+            buffer.as_ptr(),
+            aligned.as_mut_ptr() as *mut u8,
+            buffer.len(),
+        );
+    }
+    aligned
+}
+
+fuzz_target!(|input: FuzzInput| {
+    STUBS.call_once(|| {
+        set_syscall_stubs(Box::new(QuietStubs));
+    });
+
+    let mut buffer = serialize(&input);
+    let (program_id, mut accounts, instruction_data) =
+        unsafe { deserialize(buffer.as_mut_ptr() as *mut u8) };
+    for account in accounts.iter_mut() {
+        if *account.key == sysvar::rent::id() {
+            Rent::default().to_account_info(account).unwrap();
+        } else if *account.key == sysvar::clock::id() {
+            Clock::default().to_account_info(account).unwrap();
+        }
+    }
+
+    // any outcome is fine as long as it is a `ProgramResult` and not a panic
+    let _ = Processor::process(program_id, &accounts, instruction_data);
+});
@@ -0,0 +1,423 @@
+//! Typed account lists of every `LunabankInstruction`.
+//!
+//! Each list is declared once with the signer/writable/owner/address constraints of
+//! its accounts. On-chain it parses and checks `&[AccountInfo]`, client side the
+//! matching `*Keys` struct builds the `AccountMeta` list in the same order.
+//!
+//! `DepositAsset` and `WithdrawAsset` take the accounts of `DepositInstruction` and
+//! `WithdrawInstruction` followed by the `ASSET` account. `FlashLoan` passes the
+//! accounts after its own on to the borrower program.
+
+use solana_program::{
+    account_info::AccountInfo, instruction::AccountMeta, msg, program_error::ProgramError,
+    pubkey::Pubkey, system_program, sysvar,
+};
+
+use crate::utils::{
+    assert_account_key, assert_owned_by, assert_signer, assert_writable, is_token_program,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccountOwner {
+    Any,
+    /// Owned by the lunabank program
+    Program,
+    /// Owned by spl-token or Token-2022
+    Token,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AccountSpec {
+    pub name: &'static str,
+    pub is_signer: bool,
+    pub is_writable: bool,
+    pub owner: AccountOwner,
+    pub address: Option<Pubkey>,
+    /// Addresses the account may have when there is more than one
+    pub one_of: &'static [Pubkey],
+}
+
+impl AccountSpec {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            is_signer: false,
+            is_writable: false,
+            owner: AccountOwner::Any,
+            address: None,
+            one_of: &[],
+        }
+    }
+
+    pub const fn signer(mut self) -> Self {
+        self.is_signer = true;
+        self
+    }
+
+    pub const fn writable(mut self) -> Self {
+        self.is_writable = true;
+        self
+    }
+
+    pub const fn owned_by(mut self, owner: AccountOwner) -> Self {
+        self.owner = owner;
+        self
+    }
+
+    pub const fn address(mut self, address: Pubkey) -> Self {
+        self.address = Some(address);
+        self
+    }
+
+    pub const fn one_of(mut self, addresses: &'static [Pubkey]) -> Self {
+        self.one_of = addresses;
+        self
+    }
+
+    pub fn account_meta(&self, pubkey: Pubkey) -> AccountMeta {
+        AccountMeta {
+            pubkey,
+            is_signer: self.is_signer,
+            is_writable: self.is_writable,
+        }
+    }
+
+    /// Check `account`, the `index`-th account of the instruction, against the spec
+    pub fn check<'a, 'b>(
+        &self,
+        program_id: &Pubkey,
+        index: usize,
+        account: Option<&'a AccountInfo<'b>>,
+    ) -> Result<&'a AccountInfo<'b>, ProgramError> {
+        let account = account.ok_or_else(|| {
+            msg!("Account {} ({}) is missing", index, self.name);
+            ProgramError::NotEnoughAccountKeys
+        })?;
+        let report = |e: ProgramError| {
+            msg!("Account {} ({}): {}", index, self.name, e);
+            e
+        };
+
+        if self.is_signer {
+            assert_signer(account).map_err(report)?;
+        }
+        if self.is_writable {
+            assert_writable(account).map_err(report)?;
+        }
+        match self.owner {
+            AccountOwner::Any => {}
+            AccountOwner::Program => assert_owned_by(account, program_id).map_err(report)?,
+            AccountOwner::Token => {
+                if !is_token_program(account.owner) {
+                    return Err(report(ProgramError::IllegalOwner));
+                }
+            }
+        }
+        if let Some(address) = self.address {
+            assert_account_key(account, &address).map_err(report)?;
+        }
+        if !self.one_of.is_empty() && !self.one_of.contains(account.key) {
+            return Err(report(ProgramError::InvalidAccountData));
+        }
+        Ok(account)
+    }
+}
+
+macro_rules! account_context {
+    (
+        $(#[$outer:meta])*
+        $name:ident, $keys:ident {
+            $($field:ident: $spec:expr,)*
+        }
+    ) => {
+        $(#[$outer])*
+        pub struct $name<'a, 'b> {
+            $(pub $field: &'a AccountInfo<'b>,)*
+        }
+
+        impl<'a, 'b> $name<'a, 'b> {
+            pub const SPECS: &'static [AccountSpec] = &[$($spec,)*];
+
+            pub fn parse(
+                program_id: &Pubkey,
+                accounts: &'a [AccountInfo<'b>],
+            ) -> Result<Self, ProgramError> {
+                let mut specs = Self::SPECS.iter().enumerate();
+                Ok(Self {
+                    $($field: {
+                        let (index, spec) = specs.next().unwrap();
+                        spec.check(program_id, index, accounts.get(index))?
+                    },)*
+                })
+            }
+        }
+
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct $keys {
+            $(pub $field: Pubkey,)*
+        }
+
+        impl $keys {
+            pub fn to_account_metas(&self) -> Vec<AccountMeta> {
+                [$(self.$field,)*]
+                    .iter()
+                    .zip($name::SPECS)
+                    .map(|(pubkey, spec)| spec.account_meta(*pubkey))
+                    .collect()
+            }
+        }
+    };
+}
+
+const RENT_SYSVAR: AccountSpec = AccountSpec::new("rent sysvar").address(sysvar::rent::ID);
+const CLOCK_SYSVAR: AccountSpec = AccountSpec::new("clock sysvar").address(sysvar::clock::ID);
+const TOKEN_PROGRAM: AccountSpec =
+    AccountSpec::new("token program").one_of(&[spl_token::ID, spl_token_2022::ID]);
+const SYSTEM_PROGRAM: AccountSpec = AccountSpec::new("system program").address(system_program::ID);
+
+/// Asset account trailing the accounts of `DepositAsset` and `WithdrawAsset`
+pub const ASSET: AccountSpec = AccountSpec::new("asset")
+    .writable()
+    .owned_by(AccountOwner::Program);
+
+/// The `ASSET` account at `index`, for the instructions taking one
+pub fn parse_asset<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'b>],
+    index: usize,
+    with_asset: bool,
+) -> Result<Option<&'a AccountInfo<'b>>, ProgramError> {
+    if !with_asset {
+        return Ok(None);
+    }
+    ASSET
+        .check(program_id, index, accounts.get(index))
+        .map(Some)
+}
+
+account_context! {
+    /// Accounts of `LunabankInstruction::Init`
+    InitAccounts, InitKeys {
+        initializer: AccountSpec::new("initializer").signer().writable(),
+        config: AccountSpec::new("config").writable(),
+        base_token_mint: AccountSpec::new("base token mint").owned_by(AccountOwner::Token),
+        base_token_account: AccountSpec::new("base token account").writable(),
+        recipe_token_mint: AccountSpec::new("recipe token mint").writable(),
+        rent_sysvar: RENT_SYSVAR,
+        token_program: TOKEN_PROGRAM,
+        registry: AccountSpec::new("registry").writable(),
+        system_program: SYSTEM_PROGRAM,
+        pda: AccountSpec::new("bank authority"),
+    }
+}
+
+account_context! {
+    /// Accounts of `LunabankInstruction::Close`
+    CloseAccounts, CloseKeys {
+        initializer: AccountSpec::new("initializer").signer().writable(),
+        config: AccountSpec::new("config").writable().owned_by(AccountOwner::Program),
+        base_token_account: AccountSpec::new("base token account")
+            .writable()
+            .owned_by(AccountOwner::Token),
+        recipe_token_mint: AccountSpec::new("recipe token mint")
+            .writable()
+            .owned_by(AccountOwner::Token),
+        token_program: TOKEN_PROGRAM,
+        pda: AccountSpec::new("bank authority"),
+        // no entry for banks from before the registry, checked by the processor
+        registry: AccountSpec::new("registry").writable(),
+    }
+}
+
+account_context! {
+    /// Accounts of `LunabankInstruction::DepositInstruction`
+    DepositAccounts, DepositKeys {
+        payer: AccountSpec::new("payer").signer().writable(),
+        config: AccountSpec::new("config").writable().owned_by(AccountOwner::Program),
+        base_token_mint: AccountSpec::new("base token mint").owned_by(AccountOwner::Token),
+        base_token_account: AccountSpec::new("base token account")
+            .writable()
+            .owned_by(AccountOwner::Token),
+        // the payer in SOL banks, checked by the processor
+        user_base_token_account: AccountSpec::new("user base token account").writable(),
+        recipe_token_mint: AccountSpec::new("recipe token mint")
+            .writable()
+            .owned_by(AccountOwner::Token),
+        user_recipe_token_account: AccountSpec::new("user recipe token account")
+            .writable()
+            .owned_by(AccountOwner::Token),
+        deposit: AccountSpec::new("deposit").writable(),
+        pda: AccountSpec::new("bank authority"),
+        token_program: TOKEN_PROGRAM,
+        rent_sysvar: RENT_SYSVAR,
+        system_program: SYSTEM_PROGRAM,
+        clock_sysvar: CLOCK_SYSVAR,
+    }
+}
+
+account_context! {
+    /// Accounts of `LunabankInstruction::WithdrawInstruction`
+    WithdrawAccounts, WithdrawKeys {
+        payer: AccountSpec::new("payer").signer().writable(),
+        config: AccountSpec::new("config").writable().owned_by(AccountOwner::Program),
+        base_token_mint: AccountSpec::new("base token mint").owned_by(AccountOwner::Token),
+        base_token_account: AccountSpec::new("base token account")
+            .writable()
+            .owned_by(AccountOwner::Token),
+        // the unwrap PDA in SOL banks, checked by the processor
+        user_base_token_account: AccountSpec::new("user base token account").writable(),
+        recipe_token_mint: AccountSpec::new("recipe token mint")
+            .writable()
+            .owned_by(AccountOwner::Token),
+        user_recipe_token_account: AccountSpec::new("user recipe token account")
+            .writable()
+            .owned_by(AccountOwner::Token),
+        deposit: AccountSpec::new("deposit").writable(),
+        pda: AccountSpec::new("bank authority"),
+        token_program: TOKEN_PROGRAM,
+        system_program: SYSTEM_PROGRAM,
+        clock_sysvar: CLOCK_SYSVAR,
+    }
+}
+
+account_context! {
+    /// Accounts of `LunabankInstruction::Shutdown`
+    ShutdownAccounts, ShutdownKeys {
+        owner: AccountSpec::new("owner").signer(),
+        config: AccountSpec::new("config").writable().owned_by(AccountOwner::Program),
+    }
+}
+
+account_context! {
+    /// Accounts of `LunabankInstruction::Migrate`
+    MigrateAccounts, MigrateKeys {
+        payer: AccountSpec::new("payer").signer().writable(),
+        account: AccountSpec::new("account").writable().owned_by(AccountOwner::Program),
+        bank: AccountSpec::new("bank").writable().owned_by(AccountOwner::Program),
+        rent_sysvar: RENT_SYSVAR,
+        system_program: SYSTEM_PROGRAM,
+    }
+}
+
+account_context! {
+    /// Accounts of `LunabankInstruction::AddAsset`
+    AddAssetAccounts, AddAssetKeys {
+        owner: AccountSpec::new("owner").signer().writable(),
+        config: AccountSpec::new("config").writable().owned_by(AccountOwner::Program),
+        base_token_mint: AccountSpec::new("base token mint").owned_by(AccountOwner::Token),
+        asset: AccountSpec::new("asset").writable(),
+        base_token_account: AccountSpec::new("base token account").writable(),
+        recipe_token_mint: AccountSpec::new("recipe token mint").writable(),
+        rent_sysvar: RENT_SYSVAR,
+        token_program: TOKEN_PROGRAM,
+        system_program: SYSTEM_PROGRAM,
+        pda: AccountSpec::new("bank authority"),
+    }
+}
+
+account_context! {
+    /// Accounts of `LunabankInstruction::FlashLoan`, the borrower program gets the ones
+    /// that follow
+    FlashLoanAccounts, FlashLoanKeys {
+        borrower: AccountSpec::new("borrower").signer(),
+        config: AccountSpec::new("config").writable().owned_by(AccountOwner::Program),
+        base_token_mint: AccountSpec::new("base token mint").owned_by(AccountOwner::Token),
+        base_token_account: AccountSpec::new("base token account")
+            .writable()
+            .owned_by(AccountOwner::Token),
+        borrower_base_token_account: AccountSpec::new("borrower base token account")
+            .writable()
+            .owned_by(AccountOwner::Token),
+        pda: AccountSpec::new("bank authority"),
+        token_program: TOKEN_PROGRAM,
+        borrower_program: AccountSpec::new("borrower program"),
+    }
+}
+
+account_context! {
+    /// Accounts of `LunabankInstruction::RemoveAsset`
+    RemoveAssetAccounts, RemoveAssetKeys {
+        owner: AccountSpec::new("owner").signer().writable(),
+        config: AccountSpec::new("config").writable().owned_by(AccountOwner::Program),
+        asset: ASSET,
+        base_token_account: AccountSpec::new("base token account")
+            .writable()
+            .owned_by(AccountOwner::Token),
+        recipe_token_mint: AccountSpec::new("recipe token mint")
+            .writable()
+            .owned_by(AccountOwner::Token),
+        token_program: TOKEN_PROGRAM,
+        pda: AccountSpec::new("bank authority"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_metas_follow_specs() {
+        let keys = ShutdownKeys {
+            owner: Pubkey::new_unique(),
+            config: Pubkey::new_unique(),
+        };
+        assert_eq!(
+            keys.to_account_metas(),
+            vec![
+                AccountMeta::new_readonly(keys.owner, true),
+                AccountMeta::new(keys.config, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let program_id = Pubkey::new_unique();
+        let (owner_key, config_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (mut owner_lamports, mut config_lamports) = (0, 0);
+        let (mut owner_data, mut config_data) = (vec![], vec![]);
+        let system = system_program::id();
+        let owner = AccountInfo::new(
+            &owner_key,
+            true,
+            false,
+            &mut owner_lamports,
+            &mut owner_data,
+            &system,
+            false,
+            0,
+        );
+        let config = AccountInfo::new(
+            &config_key,
+            false,
+            true,
+            &mut config_lamports,
+            &mut config_data,
+            &program_id,
+            false,
+            0,
+        );
+
+        let accounts = [owner.clone(), config.clone()];
+        let parsed = ShutdownAccounts::parse(&program_id, &accounts).unwrap();
+        assert_eq!(parsed.owner.key, &owner_key);
+        assert_eq!(parsed.config.key, &config_key);
+
+        assert_eq!(
+            ShutdownAccounts::parse(&program_id, &accounts[..1]).err(),
+            Some(ProgramError::NotEnoughAccountKeys)
+        );
+
+        let accounts = [config.clone(), config.clone()];
+        assert_eq!(
+            ShutdownAccounts::parse(&program_id, &accounts).err(),
+            Some(ProgramError::MissingRequiredSignature)
+        );
+
+        let accounts = [owner.clone(), owner];
+        assert_eq!(
+            ShutdownAccounts::parse(&program_id, &accounts).err(),
+            Some(ProgramError::InvalidAccountData)
+        );
+    }
+}
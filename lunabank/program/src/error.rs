@@ -1,8 +1,14 @@
+use num_derive::FromPrimitive;
 use thiserror::Error;
 
-use solana_program::program_error::ProgramError;
+use solana_program::{
+    decode_error::DecodeError,
+    msg,
+    program_error::{PrintProgramError, ProgramError},
+};
 
-#[derive(Error, Debug, Copy, Clone)]
+/// Codes are part of the program interface, new variants go to the end
+#[derive(Error, Debug, Copy, Clone, PartialEq, Eq, FromPrimitive)]
 pub enum LunabankError {
     /// Invalid instruction
     #[error("Invalid Instruction")]
@@ -25,12 +31,81 @@ pub enum LunabankError {
     /// Incorrect Owner
     #[error("Incorrect Owner")]
     IncorrectOwner,
-    /// WaitPeriod Breach
-    #[error("WaitPeriod Breach")]
+    /// Deposit is still locked
+    #[error("Deposit is still locked")]
     WaitPeriodBreach,
     /// DataTypeMismatch
     #[error("Wrong Key value")]
     DataTypeMismatch,
+    /// Bank is shut down
+    #[error("Bank is shut down")]
+    BankShutdown,
+    /// Deposits still outstanding
+    #[error("Deposits still outstanding")]
+    DepositsOutstanding,
+    /// Account data has the wrong size
+    #[error("Wrong account data size")]
+    DataSizeMismatch,
+    /// Account is not initialized
+    #[error("Account is not initialized")]
+    UninitializedAccount,
+    /// Base token mint does not match the bank
+    #[error("Base token mint mismatch")]
+    BaseMintMismatch,
+    /// Token account is for another mint
+    #[error("Token account mint mismatch")]
+    TokenMintMismatch,
+    /// Token account belongs to someone else
+    #[error("Token account owner mismatch")]
+    TokenOwnerMismatch,
+    /// Token account has a delegate
+    #[error("Token account delegate not allowed")]
+    TokenDelegateNotAllowed,
+    /// Token account has a close authority
+    #[error("Token account close authority not allowed")]
+    TokenCloseAuthorityNotAllowed,
+    /// Vault token account does not match the bank
+    #[error("Vault mismatch")]
+    VaultMismatch,
+    /// Recipe token mint does not match the bank
+    #[error("Recipe token mint mismatch")]
+    RecipeMintMismatch,
+    /// Vault or recipe mint is not controlled by the bank authority
+    #[error("Bank authority mismatch")]
+    BankAuthorityMismatch,
+    /// Signer is not the owner of the bank
+    #[error("Bank owner mismatch")]
+    BankOwnerMismatch,
+    /// Signer is not the owner of the deposit
+    #[error("Deposit owner mismatch")]
+    DepositOwnerMismatch,
+    /// Deposit was made in another bank
+    #[error("Deposit bank mismatch")]
+    DepositBankMismatch,
+    /// Base token mint has an extension the bank can't hold
+    #[error("Unsupported mint extension")]
+    UnsupportedMintExtension,
+    /// Recipe mode is not available with the token program of the bank
+    #[error("Recipe mode not supported by the token program")]
+    RecipeModeUnsupported,
+    /// Token program is not the one of the bank
+    #[error("Token program mismatch")]
+    TokenProgramMismatch,
+    /// Asset account belongs to another bank
+    #[error("Asset bank mismatch")]
+    AssetBankMismatch,
+    /// Bank is in the middle of a flash loan
+    #[error("Bank is locked by a flash loan")]
+    BankLocked,
+    /// Vault holds less than before the flash loan plus the fee
+    #[error("Flash loan not repaid")]
+    FlashLoanNotRepaid,
+    /// V1 account, in a layout without the fields the instruction needs
+    #[error("Account not migrated")]
+    AccountNotMigrated,
+    /// Bank still has assets, they are removed before it is closed
+    #[error("Assets outstanding")]
+    AssetsOutstanding,
 }
 
 impl From<LunabankError> for ProgramError {
@@ -38,3 +113,73 @@ impl From<LunabankError> for ProgramError {
         ProgramError::Custom(e as u32)
     }
 }
+
+impl<T> DecodeError<T> for LunabankError {
+    fn type_of() -> &'static str {
+        "LunabankError"
+    }
+}
+
+impl PrintProgramError for LunabankError {
+    fn print<E>(&self) {
+        msg!("Error: {}", self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODES: &[(LunabankError, u32)] = &[
+        (LunabankError::InvalidInstruction, 0),
+        (LunabankError::NotRentExempt, 1),
+        (LunabankError::ExpectedAmountMismatch, 2),
+        (LunabankError::AmountOverflow, 3),
+        (LunabankError::TimeOverflow, 4),
+        (LunabankError::DerivedKeyInvalid, 5),
+        (LunabankError::IncorrectOwner, 6),
+        (LunabankError::WaitPeriodBreach, 7),
+        (LunabankError::DataTypeMismatch, 8),
+        (LunabankError::BankShutdown, 9),
+        (LunabankError::DepositsOutstanding, 10),
+        (LunabankError::DataSizeMismatch, 11),
+        (LunabankError::UninitializedAccount, 12),
+        (LunabankError::BaseMintMismatch, 13),
+        (LunabankError::TokenMintMismatch, 14),
+        (LunabankError::TokenOwnerMismatch, 15),
+        (LunabankError::TokenDelegateNotAllowed, 16),
+        (LunabankError::TokenCloseAuthorityNotAllowed, 17),
+        (LunabankError::VaultMismatch, 18),
+        (LunabankError::RecipeMintMismatch, 19),
+        (LunabankError::BankAuthorityMismatch, 20),
+        (LunabankError::BankOwnerMismatch, 21),
+        (LunabankError::DepositOwnerMismatch, 22),
+        (LunabankError::DepositBankMismatch, 23),
+        (LunabankError::UnsupportedMintExtension, 24),
+        (LunabankError::RecipeModeUnsupported, 25),
+        (LunabankError::TokenProgramMismatch, 26),
+        (LunabankError::AssetBankMismatch, 27),
+        (LunabankError::BankLocked, 28),
+        (LunabankError::FlashLoanNotRepaid, 29),
+        (LunabankError::AccountNotMigrated, 30),
+        (LunabankError::AssetsOutstanding, 31),
+    ];
+
+    #[test]
+    fn test_stable_codes() {
+        for (error, code) in CODES {
+            assert_eq!(ProgramError::from(*error), ProgramError::Custom(*code));
+            assert_eq!(
+                <LunabankError as DecodeError<LunabankError>>::decode_custom_error_to_enum(*code),
+                Some(*error)
+            );
+        }
+        // a new variant has to be added to the table
+        assert_eq!(
+            <LunabankError as DecodeError<LunabankError>>::decode_custom_error_to_enum(
+                CODES.len() as u32
+            ),
+            None
+        );
+    }
+}
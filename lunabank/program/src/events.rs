@@ -0,0 +1,159 @@
+//! Borsh encoded events, logged with `sol_log_data` so indexers can follow the banks
+//! without parsing `msg!` output.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{log::sol_log_data, pubkey::Pubkey};
+
+const PROGRAM_DATA_PREFIX: &str = "Program data: ";
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct BankInitialized {
+    pub bank: Pubkey,
+    pub owner: Pubkey,
+    pub base_token_mint: Pubkey,
+    pub recipe_token_mint: Pubkey,
+    pub bank_id: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct BankClosed {
+    pub bank: Pubkey,
+    pub owner: Pubkey,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct DepositEvent {
+    pub bank: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub start_timestamp: u64,
+    pub end_timestamp: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct WithdrawEvent {
+    pub bank: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub timestamp: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct FlashLoanEvent {
+    pub bank: Pubkey,
+    pub borrower: Pubkey,
+    pub borrower_program: Pubkey,
+    pub amount: u64,
+    pub fee: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub enum LunabankEvent {
+    BankInitialized(BankInitialized),
+    BankClosed(BankClosed),
+    Deposit(DepositEvent),
+    Withdraw(WithdrawEvent),
+    FlashLoan(FlashLoanEvent),
+}
+
+impl LunabankEvent {
+    pub fn emit(&self) {
+        sol_log_data(&[&self.try_to_vec().unwrap()]);
+    }
+
+    /// Decodes a single `Program data: ` log line, `None` for anything else
+    pub fn from_log_line(line: &str) -> Option<Self> {
+        let data = line.strip_prefix(PROGRAM_DATA_PREFIX)?;
+        // events are logged as one field, several would be separated by spaces
+        let data = base64::decode(data).ok()?;
+        Self::try_from_slice(&data).ok()
+    }
+
+    /// Events logged by `program_id` in the log messages of a transaction, data logged
+    /// by other programs, including the ones invoked by lunabank, is skipped
+    pub fn from_logs<S: AsRef<str>>(program_id: &Pubkey, logs: &[S]) -> Vec<Self> {
+        let program_id = program_id.to_string();
+        let mut stack: Vec<&str> = vec![];
+        let mut events = vec![];
+        for line in logs {
+            let line = line.as_ref();
+            if line.starts_with(PROGRAM_DATA_PREFIX) {
+                if stack.last() == Some(&program_id.as_str()) {
+                    events.extend(Self::from_log_line(line));
+                }
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next(), words.next()) {
+                (Some("Program"), Some(id), Some("invoke")) => stack.push(id),
+                (Some("Program"), Some(_), Some("success"))
+                | (Some("Program"), Some(_), Some("failed:")) => {
+                    stack.pop();
+                }
+                _ => {}
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deposit() -> LunabankEvent {
+        LunabankEvent::Deposit(DepositEvent {
+            bank: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            amount: 100,
+            start_timestamp: 10,
+            end_timestamp: 20,
+        })
+    }
+
+    fn data_line(event: &LunabankEvent) -> String {
+        format!(
+            "{}{}",
+            PROGRAM_DATA_PREFIX,
+            base64::encode(event.try_to_vec().unwrap())
+        )
+    }
+
+    #[test]
+    fn test_from_log_line() {
+        let event = deposit();
+        assert_eq!(
+            LunabankEvent::from_log_line(&data_line(&event)),
+            Some(event)
+        );
+        assert_eq!(LunabankEvent::from_log_line("Program log: deposit"), None);
+        assert_eq!(LunabankEvent::from_log_line("Program data: !!"), None);
+        assert_eq!(LunabankEvent::from_log_line("Program data: AQID"), None);
+    }
+
+    #[test]
+    fn test_from_logs() {
+        let program_id = Pubkey::new_unique();
+        let event = deposit();
+        let closed = LunabankEvent::BankClosed(BankClosed {
+            bank: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+        });
+        let logs = vec![
+            format!("Program {} invoke [1]", program_id),
+            "Program log: accounts.. ok".to_string(),
+            format!("Program {} invoke [2]", spl_token::id()),
+            // same bytes, but logged by the token program
+            data_line(&closed),
+            format!("Program {} success", spl_token::id()),
+            data_line(&event),
+            format!(
+                "Program {} consumed 2000 of 200000 compute units",
+                program_id
+            ),
+            format!("Program {} success", program_id),
+            data_line(&closed),
+        ];
+        assert_eq!(LunabankEvent::from_logs(&program_id, &logs), vec![event]);
+    }
+}
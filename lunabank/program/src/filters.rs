@@ -0,0 +1,338 @@
+//! `getProgramAccounts` filters over the lunabank accounts.
+//!
+//! `AccountFilter` has the shape of `solana_client::rpc_filter::RpcFilterType`, which
+//! the program can't depend on, clients map `Memcmp` to `Memcmp::new_raw_bytes`.
+//! Every helper returns the size and key filters of the account type as well, so the
+//! lists can be concatenated. The filters match the current (V2) layouts only.
+
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use std::ops::RangeInclusive;
+
+use crate::state::{Key, LunaAsset, LunaDeposit, LunaRegistryEntry, Lunabank};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AccountFilter {
+    DataSize(u64),
+    Memcmp { offset: usize, bytes: Vec<u8> },
+}
+
+impl AccountFilter {
+    fn memcmp(offset: usize, bytes: &[u8]) -> Self {
+        Self::Memcmp {
+            offset,
+            bytes: bytes.to_vec(),
+        }
+    }
+
+    /// Same check as the RPC node, for filtering accounts fetched otherwise
+    pub fn matches(&self, data: &[u8]) -> bool {
+        match self {
+            Self::DataSize(size) => data.len() as u64 == *size,
+            Self::Memcmp { offset, bytes } => {
+                data.get(*offset..*offset + bytes.len()) == Some(bytes.as_slice())
+            }
+        }
+    }
+}
+
+/// Every bank of the program
+pub fn banks() -> Vec<AccountFilter> {
+    vec![
+        AccountFilter::DataSize(Lunabank::LEN as u64),
+        AccountFilter::memcmp(Lunabank::KEY_OFFSET, &[Key::LunabankV2 as u8]),
+    ]
+}
+
+pub fn banks_by_owner(owner: &Pubkey) -> Vec<AccountFilter> {
+    let mut filters = banks();
+    filters.push(AccountFilter::memcmp(
+        Lunabank::OWNER_OFFSET,
+        owner.as_ref(),
+    ));
+    filters
+}
+
+/// Every bank of `base_token_mint`, whatever its bank id
+pub fn banks_by_base_mint(base_token_mint: &Pubkey) -> Vec<AccountFilter> {
+    let mut filters = banks();
+    filters.push(AccountFilter::memcmp(
+        Lunabank::BASE_TOKEN_MINT_OFFSET,
+        base_token_mint.as_ref(),
+    ));
+    filters
+}
+
+/// Registry entries of the banks of `base_token_mint`, one account per bank
+pub fn registry_entries_by_base_mint(base_token_mint: &Pubkey) -> Vec<AccountFilter> {
+    vec![
+        AccountFilter::DataSize(LunaRegistryEntry::LEN as u64),
+        AccountFilter::memcmp(
+            LunaRegistryEntry::KEY_OFFSET,
+            &[Key::LunaRegistryEntryV1 as u8],
+        ),
+        AccountFilter::memcmp(
+            LunaRegistryEntry::BASE_TOKEN_MINT_OFFSET,
+            base_token_mint.as_ref(),
+        ),
+    ]
+}
+
+/// Assets added to the bank `config`
+pub fn assets_by_bank(config: &Pubkey) -> Vec<AccountFilter> {
+    vec![
+        AccountFilter::DataSize(LunaAsset::LEN as u64),
+        AccountFilter::memcmp(LunaAsset::KEY_OFFSET, &[Key::LunaAssetV1 as u8]),
+        AccountFilter::memcmp(LunaAsset::BANK_OFFSET, config.as_ref()),
+    ]
+}
+
+/// Every deposit of the program, across all banks
+pub fn deposits() -> Vec<AccountFilter> {
+    vec![
+        AccountFilter::DataSize(LunaDeposit::LEN as u64),
+        AccountFilter::memcmp(LunaDeposit::KEY_OFFSET, &[Key::LunaDepositV3 as u8]),
+    ]
+}
+
+pub fn deposits_by_owner(owner: &Pubkey) -> Vec<AccountFilter> {
+    let mut filters = deposits();
+    filters.push(AccountFilter::memcmp(
+        LunaDeposit::OWNER_OFFSET,
+        owner.as_ref(),
+    ));
+    filters
+}
+
+/// Deposits made in the bank `config`, V1 deposits do not record it
+pub fn deposits_by_bank(config: &Pubkey) -> Vec<AccountFilter> {
+    let mut filters = deposits();
+    filters.push(AccountFilter::memcmp(
+        LunaDeposit::BANK_OFFSET,
+        config.as_ref(),
+    ));
+    filters
+}
+
+/// Seconds covered by `deposits_unlocking_in_window`, `granularity` low order bytes of
+/// the timestamp are left out of the comparison. Inclusive, the last window ends at
+/// `u64::MAX`.
+pub fn unlock_window(timestamp: u64, granularity: usize) -> RangeInclusive<u64> {
+    assert!(granularity < 8, "granularity is at most 7 bytes");
+    let size = 1u64 << (8 * granularity);
+    let start = timestamp & !(size - 1);
+    start..=start | (size - 1)
+}
+
+/// Deposits with `end_timestamp` in `unlock_window(timestamp, granularity)`.
+///
+/// Memcmp can only match bytes exactly, so the window is aligned on `256^granularity`
+/// seconds: 1 is about 4 minutes, 2 about 18 hours, 3 about 194 days. Pick the
+/// smallest granularity covering the range of interest and drop the surplus client side.
+pub fn deposits_unlocking_in_window(timestamp: u64, granularity: usize) -> Vec<AccountFilter> {
+    let window = unlock_window(timestamp, granularity);
+    let mut filters = deposits();
+    filters.push(AccountFilter::memcmp(
+        LunaDeposit::END_TIMESTAMP_OFFSET + granularity,
+        &window.start().to_le_bytes()[granularity..],
+    ));
+    filters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::RecipeMode;
+
+    fn packed<T: Pack>(value: T) -> Vec<u8> {
+        let mut data = vec![0; T::LEN];
+        T::pack(value, &mut data).unwrap();
+        data
+    }
+
+    fn bank(owner: Pubkey, base_token_mint: Pubkey) -> Vec<u8> {
+        packed(Lunabank {
+            key: Key::LunabankV2,
+            owner,
+            base_token_mint,
+            recipe_token_mint: Pubkey::new_unique(),
+            base_token_account: Pubkey::new_unique(),
+            coef: 1,
+            is_shutdown: true,
+            active_deposits: 2,
+            bank_id: 3,
+            pda_bump_seed: 254,
+            recipe_mode: RecipeMode::Frozen,
+            token_program: spl_token::id(),
+            is_locked: true,
+            asset_count: 4,
+            reserved: [0; 22],
+        })
+    }
+
+    fn deposit(owner: Pubkey, end_timestamp: u64) -> Vec<u8> {
+        deposit_in(Pubkey::new_unique(), owner, end_timestamp)
+    }
+
+    fn deposit_in(bank: Pubkey, owner: Pubkey, end_timestamp: u64) -> Vec<u8> {
+        packed(LunaDeposit {
+            key: Key::LunaDepositV3,
+            owner,
+            amount: 100,
+            start_timestamp: 10,
+            end_timestamp,
+            bump_seed: 253,
+            bank,
+            base_token_mint: Pubkey::new_unique(),
+            reserved: [0; 32],
+        })
+    }
+
+    fn matches(filters: &[AccountFilter], data: &[u8]) -> bool {
+        filters.iter().all(|filter| filter.matches(data))
+    }
+
+    #[test]
+    fn test_offsets() {
+        let owner = Pubkey::new_unique();
+        let base_token_mint = Pubkey::new_unique();
+        let data = bank(owner, base_token_mint);
+        let field = |offset: usize, len: usize| &data[offset..offset + len];
+        assert_eq!(field(Lunabank::KEY_OFFSET, 1), [Key::LunabankV2 as u8]);
+        assert_eq!(field(Lunabank::OWNER_OFFSET, 32), owner.as_ref());
+        assert_eq!(
+            field(Lunabank::BASE_TOKEN_MINT_OFFSET, 32),
+            base_token_mint.as_ref()
+        );
+        assert_eq!(field(Lunabank::COEF_OFFSET, 8), 1u64.to_le_bytes());
+        assert_eq!(field(Lunabank::IS_SHUTDOWN_OFFSET, 1), [1]);
+        assert_eq!(
+            field(Lunabank::ACTIVE_DEPOSITS_OFFSET, 8),
+            2u64.to_le_bytes()
+        );
+        assert_eq!(field(Lunabank::BANK_ID_OFFSET, 8), 3u64.to_le_bytes());
+        assert_eq!(
+            field(Lunabank::TOKEN_PROGRAM_OFFSET, 32),
+            spl_token::id().as_ref()
+        );
+        assert_eq!(field(Lunabank::IS_LOCKED_OFFSET, 1), [1]);
+        assert_eq!(field(Lunabank::ASSET_COUNT_OFFSET, 8), 4u64.to_le_bytes());
+
+        let bank = Pubkey::new_unique();
+        let data = deposit_in(bank, owner, 20);
+        let field = |offset: usize, len: usize| &data[offset..offset + len];
+        assert_eq!(
+            field(LunaDeposit::KEY_OFFSET, 1),
+            [Key::LunaDepositV3 as u8]
+        );
+        assert_eq!(field(LunaDeposit::OWNER_OFFSET, 32), owner.as_ref());
+        assert_eq!(field(LunaDeposit::AMOUNT_OFFSET, 8), 100u64.to_le_bytes());
+        assert_eq!(
+            field(LunaDeposit::START_TIMESTAMP_OFFSET, 8),
+            10u64.to_le_bytes()
+        );
+        assert_eq!(
+            field(LunaDeposit::END_TIMESTAMP_OFFSET, 8),
+            20u64.to_le_bytes()
+        );
+        assert_eq!(field(LunaDeposit::BUMP_SEED_OFFSET, 1), [253]);
+        assert_eq!(field(LunaDeposit::BANK_OFFSET, 32), bank.as_ref());
+    }
+
+    #[test]
+    fn test_bank_filters() {
+        let (owner, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let data = bank(owner, mint);
+        assert!(matches(&banks(), &data));
+        assert!(matches(&banks_by_owner(&owner), &data));
+        assert!(matches(&banks_by_base_mint(&mint), &data));
+        assert!(!matches(&banks_by_owner(&mint), &data));
+        assert!(!matches(&banks_by_base_mint(&owner), &data));
+        assert!(!matches(&banks(), &deposit(owner, 20)));
+
+        // V1 banks are migrated first
+        assert!(!matches(&banks(), &data[..Lunabank::LEN_V1]));
+    }
+
+    #[test]
+    fn test_deposit_filters() {
+        let owner = Pubkey::new_unique();
+        let data = deposit(owner, 20);
+        assert!(matches(&deposits(), &data));
+        assert!(matches(&deposits_by_owner(&owner), &data));
+        assert!(!matches(&deposits_by_owner(&Pubkey::new_unique()), &data));
+        assert!(!matches(&deposits(), &bank(owner, owner)));
+
+        let config = Pubkey::new_unique();
+        assert!(matches(
+            &deposits_by_bank(&config),
+            &deposit_in(config, owner, 20)
+        ));
+        assert!(!matches(&deposits_by_bank(&config), &data));
+    }
+
+    #[test]
+    fn test_asset_filters() {
+        let config = Pubkey::new_unique();
+        let data = packed(LunaAsset {
+            key: Key::LunaAssetV1,
+            bank: config,
+            base_token_mint: Pubkey::new_unique(),
+            recipe_token_mint: Pubkey::new_unique(),
+            base_token_account: Pubkey::new_unique(),
+            active_deposits: 0,
+            bump_seed: 255,
+            reserved: [0; 32],
+        });
+        assert!(matches(&assets_by_bank(&config), &data));
+        assert!(!matches(&assets_by_bank(&Pubkey::new_unique()), &data));
+        assert!(!matches(
+            &assets_by_bank(&config),
+            &deposit_in(config, config, 20)
+        ));
+    }
+
+    #[test]
+    fn test_registry_filters() {
+        let (config, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let data = packed(LunaRegistryEntry {
+            key: Key::LunaRegistryEntryV1,
+            bank: config,
+            base_token_mint: mint,
+            bank_id: 3,
+        });
+        assert!(matches(&registry_entries_by_base_mint(&mint), &data));
+        assert!(!matches(&registry_entries_by_base_mint(&config), &data));
+        assert!(!matches(
+            &registry_entries_by_base_mint(&mint),
+            &bank(config, mint)
+        ));
+    }
+
+    #[test]
+    fn test_unlock_window() {
+        assert_eq!(unlock_window(0x1234_5678, 0), 0x1234_5678..=0x1234_5678);
+        assert_eq!(unlock_window(0x1234_5678, 2), 0x1234_0000..=0x1234_ffff);
+        assert_eq!(unlock_window(u64::MAX, 1), u64::MAX - 255..=u64::MAX);
+        assert_eq!(unlock_window(u64::MAX, 0), u64::MAX..=u64::MAX);
+
+        let owner = Pubkey::new_unique();
+        let filters = deposits_unlocking_in_window(0x1234_5678, 2);
+        for end_timestamp in [0x1234_0000, 0x1234_5678, 0x1234_ffff] {
+            assert!(matches(&filters, &deposit(owner, end_timestamp)));
+        }
+        for end_timestamp in [0x1233_ffff, 0x1235_0000, 0x0001_1234_5678] {
+            assert!(!matches(&filters, &deposit(owner, end_timestamp)));
+        }
+
+        let filters = deposits_unlocking_in_window(20, 0);
+        assert!(matches(&filters, &deposit(owner, 20)));
+        assert!(!matches(&filters, &deposit(owner, 21)));
+
+        // the filter matches every timestamp of the window, the last one included
+        let filters = deposits_unlocking_in_window(u64::MAX, 1);
+        for end_timestamp in unlock_window(u64::MAX, 1) {
+            assert!(matches(&filters, &deposit(owner, end_timestamp)));
+        }
+        assert!(!matches(&filters, &deposit(owner, u64::MAX - 256)));
+    }
+}
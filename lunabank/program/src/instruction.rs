@@ -1,47 +1,76 @@
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program, sysvar,
+};
 //use solana_program::program_error::ProgramError;
 //use std::convert::TryInto;
 
 //use crate::error::LunabankError::InvalidInstruction;
+use crate::accounts::{
+    AddAssetKeys, CloseKeys, DepositKeys, FlashLoanKeys, InitKeys, MigrateKeys, RemoveAssetKeys,
+    ShutdownKeys, WithdrawKeys, ASSET,
+};
+use crate::state::{
+    find_asset_address, find_bank_address, find_bank_authority_address, find_deposit_address,
+    find_recipe_mint_address, find_registry_address, find_unwrap_address, find_vault_address,
+    RecipeMode,
+};
 
-#[derive(BorshSerialize, BorshDeserialize, Clone)]
+// the `allow(dead_code)` on variant fields is for the structs `BorshSchema` copies them
+// into, which are never read
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, Clone, Debug, PartialEq)]
 pub enum LunabankInstruction {
     /// Init account.
     ///
+    /// Creates the config account, the vault, the recipe mint and the registry entry of the bank.
+    /// The vault and the recipe mint are owned by the "lunaowner" PDA from the start, and belong to
+    /// the token program of the base token mint, spl-token or Token-2022.
     ///
     /// Accounts expected:
     ///
-    /// 0. `[signer]` The account of the person initializing the contract
-    /// 1. `[writable]` The config account, it will hold all necessary info about the contract.
+    /// 0. `[signer, writable]` The account of the person initializing the contract, pays for the new accounts
+    /// 1. `[writable]` PDA config account ("lunabank" + base token mint + bank_id), it will hold all necessary info about the contract.
     /// 2. `[]` base token mint
-    /// 3. `[]` base token account
-    /// 4. `[writable]` recipe token mint
+    /// 3. `[writable]` PDA base token account (config + "vault")
+    /// 4. `[writable]` PDA recipe token mint (config + "recipe")
     /// 5. `[]` The rent sysvar
-    /// 6. `[]` The token program
-    Init,
+    /// 6. `[]` The token program, owner of the base token mint
+    /// 7. `[writable]` PDA registry entry ("registry" + base token mint + bank_id)
+    /// 8. `[]` The system program
+    /// 9. `[]` PDA owner (config + "lunaowner")
+    Init {
+        #[allow(dead_code)]
+        bank_id: u64,
+    },
     /// Close account, return all the
     ///
+    /// Only possible once every deposit has been withdrawn and every asset removed. The registry
+    /// entry is closed as well, banks from before the registry have none.
     ///
     /// Accounts expected:
     ///
-    /// 0. `[signer]` The account of the person initializing the contract
+    /// 0. `[signer, writable]` The account of the person initializing the contract
     /// 1. `[writable]` The config account, it will hold all necessary info about the contract.
     /// 2. `[writable]` base token account
     /// 3. `[writable]` recipe token mint
     /// 4. `[]` The token program
     /// 5. `[]` PDA owner (config + "lunaowner")
+    /// 6. `[writable]` PDA registry entry ("registry" + base token mint + bank_id)
     ///
     Close,
     /// Deposit,
     ///
+    /// Banks of the native mint take `amount` lamports from the payer and wrap them in the vault.
     ///
     /// Accounts expected:
     ///
-    /// 0. `[signer]` The account of the person initializing the contract
-    /// 1. `[]` The config account, it will hold all necessary info about the contract.
+    /// 0. `[signer, writable]` The account of the person initializing the contract
+    /// 1. `[writable]` The config account, it will hold all necessary info about the contract.
     /// 2. `[]` base token mint
     /// 3. `[writable]` base token account
-    /// 4. `[writable]` user base token account
+    /// 4. `[writable]` user base token account, the payer for banks of the native mint
     /// 5. `[writable]` recipe token mint
     /// 6. `[writable]` user recipe token account
     /// 7. `[writable]` PDA user subaccount owner (config + initializer + "deposit")
@@ -51,19 +80,29 @@ pub enum LunabankInstruction {
     /// 11. `[]` The system program
     /// 12. `[]` The system clock program
     ///
-    DepositInstruction { amount: u64, deposit_time: u64 },
+    DepositInstruction {
+        #[allow(dead_code)]
+        amount: u64,
+        #[allow(dead_code)]
+        deposit_time: u64,
+    },
     /// Withdraw,
     ///
+    /// The lock time is ignored once the bank is shut down. Banks of the native mint pay the
+    /// deposit back in lamports to the payer. In `RecipeMode::Transferable` banks the
+    /// recipe tokens are burnt from any account of the recipe mint the payer controls, and
+    /// the account is not closed.
     ///
     /// Accounts expected:
     ///
-    /// 0. `[signer]` The account of the person initializing the contract
-    /// 1. `[]` The config account, it will hold all necessary info about the contract.
+    /// 0. `[signer, writable]` The account of the person initializing the contract
+    /// 1. `[writable]` The config account, it will hold all necessary info about the contract.
     /// 2. `[]` base token mint
     /// 3. `[writable]` base token account
-    /// 4. `[writable]` user base token account
+    /// 4. `[writable]` user base token account, for banks of the native mint the PDA
+    ///    (config + initializer + "unwrap") of the temporary wrapped SOL account
     /// 5. `[writable]` recipe token mint
-    /// 6. `[writable]` user recipe token account
+    /// 6. `[writable]` user recipe token account, owned by or delegated to the payer
     /// 7. `[writable]` PDA user subaccount owner (config + initializer + "deposit")
     /// 8. `[]` PDA owner (config + "lunaowner")
     /// 9. `[]` The token program
@@ -71,4 +110,698 @@ pub enum LunabankInstruction {
     /// 11. `[]` The system clock program
     ///
     WithdrawInstruction,
+    /// Shutdown, stop accepting deposits and let every depositor withdraw immediately
+    ///
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The owner of the bank
+    /// 1. `[writable]` The config account
+    ///
+    Shutdown,
+    /// Migrate, rewrite a V1 config or an older deposit account in the current layout
+    ///
+    /// The account is resized, the payer tops up its rent. Banks get the bump seed of their
+    /// authority. Deposits get the bank and its base mint filled in, the bank has to be
+    /// migrated first. V1 deposits also get their bump seed and are counted in the active
+    /// deposits of the bank. Accounts already in the current layout are left alone.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The payer of the additional rent
+    /// 1. `[writable]` The config or deposit account
+    /// 2. `[writable]` The config account of the bank, the same as 1. when migrating a bank
+    /// 3. `[]` The rent sysvar
+    /// 4. `[]` The system program
+    ///
+    Migrate,
+    /// Init with the recipe mode chosen, `Init` creates `RecipeMode::Frozen` banks.
+    ///
+    /// `RecipeMode::NonTransferable` needs a base token mint of Token-2022.
+    /// `RecipeMode::Transferable` banks take no freeze authority over the recipe mint.
+    ///
+    /// Accounts expected: the same as `Init`
+    InitWithOptions {
+        #[allow(dead_code)]
+        bank_id: u64,
+        #[allow(dead_code)]
+        recipe_mode: RecipeMode,
+    },
+    /// AddAsset, accept deposits of another base token mint in the bank
+    ///
+    /// Creates the asset account with a vault and a recipe mint of its own, both owned by
+    /// the bank authority. The owner, the shutdown flag, the recipe mode and the token
+    /// program of the bank apply to the asset.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The owner of the bank, pays for the new accounts
+    /// 1. `[writable]` The config account
+    /// 2. `[]` base token mint of the asset, of the token program of the bank
+    /// 3. `[writable]` PDA asset account (config + base token mint + "asset")
+    /// 4. `[writable]` PDA base token account (asset + "vault")
+    /// 5. `[writable]` PDA recipe token mint (asset + "recipe")
+    /// 6. `[]` The rent sysvar
+    /// 7. `[]` The token program
+    /// 8. `[]` The system program
+    /// 9. `[]` PDA owner (config + "lunaowner")
+    AddAsset,
+    /// Deposit in an asset of the bank
+    ///
+    /// Accounts expected: the ones of `DepositInstruction` with the base token mint, the
+    /// vault and the recipe mint of the asset, and the PDA user subaccount
+    /// (asset + initializer + "deposit"), followed by
+    ///
+    /// 13. `[writable]` The asset account
+    DepositAsset {
+        #[allow(dead_code)]
+        amount: u64,
+        #[allow(dead_code)]
+        deposit_time: u64,
+    },
+    /// Withdraw from an asset of the bank
+    ///
+    /// Accounts expected: the ones of `WithdrawInstruction` with the base token mint, the
+    /// vault and the recipe mint of the asset, and the PDA user subaccount
+    /// (asset + initializer + "deposit"), followed by
+    ///
+    /// 12. `[writable]` The asset account
+    WithdrawAsset,
+    /// FlashLoan, lend `amount` from the vault for the length of a call to the borrower program
+    ///
+    /// Sends `amount` to the borrower base token account and invokes the borrower program with
+    /// the accounts following 7., as they are passed, and the little endian `amount` and fee as
+    /// instruction data. The vault has to hold `amount` plus the fee more than at the call when
+    /// the borrower program returns, the fee is `FLASH_LOAN_FEE_BPS` of `amount` rounded up.
+    /// The bank is locked during the call and rejects every instruction, flash loans included.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The borrower
+    /// 1. `[writable]` The config account
+    /// 2. `[]` base token mint
+    /// 3. `[writable]` base token account
+    /// 4. `[writable]` borrower base token account, receives the loan
+    /// 5. `[]` PDA owner (config + "lunaowner")
+    /// 6. `[]` The token program
+    /// 7. `[]` The borrower program
+    /// 8. ... The accounts of the borrower program
+    FlashLoan {
+        #[allow(dead_code)]
+        amount: u64,
+    },
+    /// RemoveAsset, stop accepting deposits of an asset and close its account
+    ///
+    /// Only possible once every deposit of the asset has been withdrawn. The vault and the
+    /// recipe mint of the asset are handed over to the owner, like `Close` does with the
+    /// ones of the bank.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer, writable]` The owner of the bank, receives the rent of the asset account
+    /// 1. `[writable]` The config account
+    /// 2. `[writable]` PDA asset account (config + base token mint + "asset")
+    /// 3. `[writable]` PDA base token account (asset + "vault")
+    /// 4. `[writable]` PDA recipe token mint (asset + "recipe")
+    /// 5. `[]` The token program
+    /// 6. `[]` PDA owner (config + "lunaowner")
+    RemoveAsset,
+}
+
+impl LunabankInstruction {
+    fn to_instruction(&self, program_id: &Pubkey, accounts: Vec<AccountMeta>) -> Instruction {
+        Instruction {
+            program_id: *program_id,
+            accounts,
+            data: self.try_to_vec().unwrap(),
+        }
+    }
+}
+
+fn find_bank_accounts(program_id: &Pubkey, config: &Pubkey) -> (Pubkey, Pubkey, Pubkey) {
+    (
+        find_vault_address(program_id, config).0,
+        find_recipe_mint_address(program_id, config).0,
+        find_bank_authority_address(program_id, config).0,
+    )
+}
+
+fn init_account_metas(
+    program_id: &Pubkey,
+    token_program: &Pubkey,
+    initializer: &Pubkey,
+    base_token_mint: &Pubkey,
+    bank_id: u64,
+) -> Vec<AccountMeta> {
+    let (config, _) = find_bank_address(program_id, base_token_mint, bank_id);
+    let (base_token_account, recipe_token_mint, pda) = find_bank_accounts(program_id, &config);
+    let (registry, _) = find_registry_address(program_id, base_token_mint, bank_id);
+    let keys = InitKeys {
+        initializer: *initializer,
+        config,
+        base_token_mint: *base_token_mint,
+        base_token_account,
+        recipe_token_mint,
+        rent_sysvar: sysvar::rent::id(),
+        token_program: *token_program,
+        registry,
+        system_program: system_program::id(),
+        pda,
+    };
+    keys.to_account_metas()
+}
+
+/// Creates an `Init` instruction, the config account is derived from `base_token_mint` and `bank_id`.
+/// `token_program` is the owner of `base_token_mint`.
+pub fn init(
+    program_id: &Pubkey,
+    token_program: &Pubkey,
+    initializer: &Pubkey,
+    base_token_mint: &Pubkey,
+    bank_id: u64,
+) -> Instruction {
+    let accounts = init_account_metas(
+        program_id,
+        token_program,
+        initializer,
+        base_token_mint,
+        bank_id,
+    );
+    LunabankInstruction::Init { bank_id }.to_instruction(program_id, accounts)
+}
+
+/// Creates an `InitWithOptions` instruction, see `init`
+pub fn init_with_options(
+    program_id: &Pubkey,
+    token_program: &Pubkey,
+    initializer: &Pubkey,
+    base_token_mint: &Pubkey,
+    bank_id: u64,
+    recipe_mode: RecipeMode,
+) -> Instruction {
+    let accounts = init_account_metas(
+        program_id,
+        token_program,
+        initializer,
+        base_token_mint,
+        bank_id,
+    );
+    LunabankInstruction::InitWithOptions {
+        bank_id,
+        recipe_mode,
+    }
+    .to_instruction(program_id, accounts)
+}
+
+/// Creates a `Close` instruction, `base_token_mint` and `bank_id` are the ones stored in the
+/// config account
+pub fn close(
+    program_id: &Pubkey,
+    token_program: &Pubkey,
+    initializer: &Pubkey,
+    config: &Pubkey,
+    base_token_mint: &Pubkey,
+    bank_id: u64,
+) -> Instruction {
+    let (base_token_account, recipe_token_mint, pda) = find_bank_accounts(program_id, config);
+    let (registry, _) = find_registry_address(program_id, base_token_mint, bank_id);
+    let keys = CloseKeys {
+        initializer: *initializer,
+        config: *config,
+        base_token_account,
+        recipe_token_mint,
+        token_program: *token_program,
+        pda,
+        registry,
+    };
+    LunabankInstruction::Close.to_instruction(program_id, keys.to_account_metas())
+}
+
+/// Deposit accounts in `pool`, the config account of the bank or one of its assets
+#[allow(clippy::too_many_arguments)]
+fn deposit_keys(
+    program_id: &Pubkey,
+    token_program: &Pubkey,
+    payer: &Pubkey,
+    config: &Pubkey,
+    pool: &Pubkey,
+    base_token_mint: &Pubkey,
+    user_base_token_account: &Pubkey,
+    user_recipe_token_account: &Pubkey,
+) -> DepositKeys {
+    let (deposit, _) = find_deposit_address(program_id, pool, payer);
+    DepositKeys {
+        payer: *payer,
+        config: *config,
+        base_token_mint: *base_token_mint,
+        base_token_account: find_vault_address(program_id, pool).0,
+        user_base_token_account: *user_base_token_account,
+        recipe_token_mint: find_recipe_mint_address(program_id, pool).0,
+        user_recipe_token_account: *user_recipe_token_account,
+        deposit,
+        pda: find_bank_authority_address(program_id, config).0,
+        token_program: *token_program,
+        rent_sysvar: sysvar::rent::id(),
+        system_program: system_program::id(),
+        clock_sysvar: sysvar::clock::id(),
+    }
+}
+
+/// Withdraw accounts in `pool`, see `deposit_keys`
+#[allow(clippy::too_many_arguments)]
+fn withdraw_keys(
+    program_id: &Pubkey,
+    token_program: &Pubkey,
+    payer: &Pubkey,
+    config: &Pubkey,
+    pool: &Pubkey,
+    base_token_mint: &Pubkey,
+    user_base_token_account: &Pubkey,
+    user_recipe_token_account: &Pubkey,
+) -> WithdrawKeys {
+    let (deposit, _) = find_deposit_address(program_id, pool, payer);
+    WithdrawKeys {
+        payer: *payer,
+        config: *config,
+        base_token_mint: *base_token_mint,
+        base_token_account: find_vault_address(program_id, pool).0,
+        user_base_token_account: *user_base_token_account,
+        recipe_token_mint: find_recipe_mint_address(program_id, pool).0,
+        user_recipe_token_account: *user_recipe_token_account,
+        deposit,
+        pda: find_bank_authority_address(program_id, config).0,
+        token_program: *token_program,
+        system_program: system_program::id(),
+        clock_sysvar: sysvar::clock::id(),
+    }
+}
+
+/// Creates a `DepositInstruction` instruction
+#[allow(clippy::too_many_arguments)]
+pub fn deposit(
+    program_id: &Pubkey,
+    token_program: &Pubkey,
+    payer: &Pubkey,
+    config: &Pubkey,
+    base_token_mint: &Pubkey,
+    user_base_token_account: &Pubkey,
+    user_recipe_token_account: &Pubkey,
+    amount: u64,
+    deposit_time: u64,
+) -> Instruction {
+    let keys = deposit_keys(
+        program_id,
+        token_program,
+        payer,
+        config,
+        config,
+        base_token_mint,
+        user_base_token_account,
+        user_recipe_token_account,
+    );
+    LunabankInstruction::DepositInstruction {
+        amount,
+        deposit_time,
+    }
+    .to_instruction(program_id, keys.to_account_metas())
+}
+
+/// Creates a `WithdrawInstruction` instruction
+pub fn withdraw(
+    program_id: &Pubkey,
+    token_program: &Pubkey,
+    payer: &Pubkey,
+    config: &Pubkey,
+    base_token_mint: &Pubkey,
+    user_base_token_account: &Pubkey,
+    user_recipe_token_account: &Pubkey,
+) -> Instruction {
+    let keys = withdraw_keys(
+        program_id,
+        token_program,
+        payer,
+        config,
+        config,
+        base_token_mint,
+        user_base_token_account,
+        user_recipe_token_account,
+    );
+    LunabankInstruction::WithdrawInstruction.to_instruction(program_id, keys.to_account_metas())
+}
+
+/// Creates an `AddAsset` instruction for `base_token_mint`, of the token program of the bank
+pub fn add_asset(
+    program_id: &Pubkey,
+    token_program: &Pubkey,
+    owner: &Pubkey,
+    config: &Pubkey,
+    base_token_mint: &Pubkey,
+) -> Instruction {
+    let (asset, _) = find_asset_address(program_id, config, base_token_mint);
+    let keys = AddAssetKeys {
+        owner: *owner,
+        config: *config,
+        base_token_mint: *base_token_mint,
+        asset,
+        base_token_account: find_vault_address(program_id, &asset).0,
+        recipe_token_mint: find_recipe_mint_address(program_id, &asset).0,
+        rent_sysvar: sysvar::rent::id(),
+        token_program: *token_program,
+        system_program: system_program::id(),
+        pda: find_bank_authority_address(program_id, config).0,
+    };
+    LunabankInstruction::AddAsset.to_instruction(program_id, keys.to_account_metas())
+}
+
+/// Creates a `RemoveAsset` instruction for the asset of `base_token_mint`
+pub fn remove_asset(
+    program_id: &Pubkey,
+    token_program: &Pubkey,
+    owner: &Pubkey,
+    config: &Pubkey,
+    base_token_mint: &Pubkey,
+) -> Instruction {
+    let (asset, _) = find_asset_address(program_id, config, base_token_mint);
+    let keys = RemoveAssetKeys {
+        owner: *owner,
+        config: *config,
+        asset,
+        base_token_account: find_vault_address(program_id, &asset).0,
+        recipe_token_mint: find_recipe_mint_address(program_id, &asset).0,
+        token_program: *token_program,
+        pda: find_bank_authority_address(program_id, config).0,
+    };
+    LunabankInstruction::RemoveAsset.to_instruction(program_id, keys.to_account_metas())
+}
+
+/// Creates a `DepositAsset` instruction in the asset of `base_token_mint`
+#[allow(clippy::too_many_arguments)]
+pub fn deposit_asset(
+    program_id: &Pubkey,
+    token_program: &Pubkey,
+    payer: &Pubkey,
+    config: &Pubkey,
+    base_token_mint: &Pubkey,
+    user_base_token_account: &Pubkey,
+    user_recipe_token_account: &Pubkey,
+    amount: u64,
+    deposit_time: u64,
+) -> Instruction {
+    let (asset, _) = find_asset_address(program_id, config, base_token_mint);
+    let mut accounts = deposit_keys(
+        program_id,
+        token_program,
+        payer,
+        config,
+        &asset,
+        base_token_mint,
+        user_base_token_account,
+        user_recipe_token_account,
+    )
+    .to_account_metas();
+    accounts.push(ASSET.account_meta(asset));
+    LunabankInstruction::DepositAsset {
+        amount,
+        deposit_time,
+    }
+    .to_instruction(program_id, accounts)
+}
+
+/// Creates a `WithdrawAsset` instruction from the asset of `base_token_mint`
+pub fn withdraw_asset(
+    program_id: &Pubkey,
+    token_program: &Pubkey,
+    payer: &Pubkey,
+    config: &Pubkey,
+    base_token_mint: &Pubkey,
+    user_base_token_account: &Pubkey,
+    user_recipe_token_account: &Pubkey,
+) -> Instruction {
+    let (asset, _) = find_asset_address(program_id, config, base_token_mint);
+    let mut accounts = withdraw_keys(
+        program_id,
+        token_program,
+        payer,
+        config,
+        &asset,
+        base_token_mint,
+        user_base_token_account,
+        user_recipe_token_account,
+    )
+    .to_account_metas();
+    accounts.push(ASSET.account_meta(asset));
+    LunabankInstruction::WithdrawAsset.to_instruction(program_id, accounts)
+}
+
+/// Creates a `FlashLoan` instruction, `borrower_accounts` are passed on to `borrower_program`
+#[allow(clippy::too_many_arguments)]
+pub fn flash_loan(
+    program_id: &Pubkey,
+    token_program: &Pubkey,
+    borrower: &Pubkey,
+    config: &Pubkey,
+    base_token_mint: &Pubkey,
+    borrower_base_token_account: &Pubkey,
+    borrower_program: &Pubkey,
+    borrower_accounts: &[AccountMeta],
+    amount: u64,
+) -> Instruction {
+    let (base_token_account, _, pda) = find_bank_accounts(program_id, config);
+    let keys = FlashLoanKeys {
+        borrower: *borrower,
+        config: *config,
+        base_token_mint: *base_token_mint,
+        base_token_account,
+        borrower_base_token_account: *borrower_base_token_account,
+        pda,
+        token_program: *token_program,
+        borrower_program: *borrower_program,
+    };
+    let mut accounts = keys.to_account_metas();
+    accounts.extend_from_slice(borrower_accounts);
+    LunabankInstruction::FlashLoan { amount }.to_instruction(program_id, accounts)
+}
+
+/// Creates a `DepositInstruction` of `amount` lamports in a bank of the native mint
+pub fn deposit_sol(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    config: &Pubkey,
+    user_recipe_token_account: &Pubkey,
+    amount: u64,
+    deposit_time: u64,
+) -> Instruction {
+    deposit(
+        program_id,
+        &spl_token::id(),
+        payer,
+        config,
+        &spl_token::native_mint::id(),
+        payer,
+        user_recipe_token_account,
+        amount,
+        deposit_time,
+    )
+}
+
+/// Creates a `WithdrawInstruction` from a bank of the native mint, paid out in lamports
+pub fn withdraw_sol(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    config: &Pubkey,
+    user_recipe_token_account: &Pubkey,
+) -> Instruction {
+    let (unwrap, _) = find_unwrap_address(program_id, config, payer);
+    withdraw(
+        program_id,
+        &spl_token::id(),
+        payer,
+        config,
+        &spl_token::native_mint::id(),
+        &unwrap,
+        user_recipe_token_account,
+    )
+}
+
+/// Creates a `Shutdown` instruction
+pub fn shutdown(program_id: &Pubkey, owner: &Pubkey, config: &Pubkey) -> Instruction {
+    let keys = ShutdownKeys {
+        owner: *owner,
+        config: *config,
+    };
+    LunabankInstruction::Shutdown.to_instruction(program_id, keys.to_account_metas())
+}
+
+/// Creates a `Migrate` instruction for a config or deposit account of the bank `config`
+pub fn migrate(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    account: &Pubkey,
+    config: &Pubkey,
+) -> Instruction {
+    let keys = MigrateKeys {
+        payer: *payer,
+        account: *account,
+        bank: *config,
+        rent_sysvar: sysvar::rent::id(),
+        system_program: system_program::id(),
+    };
+    LunabankInstruction::Migrate.to_instruction(program_id, keys.to_account_metas())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accounts::{AddAssetAccounts, DepositAccounts, InitAccounts, WithdrawAccounts};
+
+    #[test]
+    fn test_deposit_withdraw_share_accounts() {
+        let program_id = Pubkey::new_unique();
+        let (payer, config, mint) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (user_base, user_recipe) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        let deposit = deposit(
+            &program_id,
+            &spl_token::id(),
+            &payer,
+            &config,
+            &mint,
+            &user_base,
+            &user_recipe,
+            100,
+            10,
+        );
+        assert_eq!(deposit.accounts.len(), DepositAccounts::SPECS.len());
+        assert_eq!(
+            LunabankInstruction::try_from_slice(&deposit.data).unwrap(),
+            LunabankInstruction::DepositInstruction {
+                amount: 100,
+                deposit_time: 10
+            }
+        );
+
+        let withdraw = withdraw(
+            &program_id,
+            &spl_token::id(),
+            &payer,
+            &config,
+            &mint,
+            &user_base,
+            &user_recipe,
+        );
+        assert_eq!(withdraw.accounts.len(), WithdrawAccounts::SPECS.len());
+        // every account but the rent sysvar is passed at the same position
+        let mut deposit_accounts = deposit.accounts.clone();
+        deposit_accounts.remove(10);
+        assert_eq!(withdraw.accounts, deposit_accounts);
+    }
+
+    #[test]
+    fn test_init_close_share_accounts() {
+        let program_id = Pubkey::new_unique();
+        let (initializer, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        let token_program = spl_token_2022::id();
+        let init = init(&program_id, &token_program, &initializer, &mint, 3);
+        assert_eq!(init.accounts.len(), InitAccounts::SPECS.len());
+        let config = init.accounts[1].pubkey;
+        assert_eq!(config, find_bank_address(&program_id, &mint, 3).0);
+
+        let close = close(&program_id, &token_program, &initializer, &config, &mint, 3);
+        let key = |ix: &Instruction, i: usize| ix.accounts[i].pubkey;
+        // vault, recipe mint, token program, bank authority and registry
+        assert_eq!(key(&close, 2), key(&init, 3));
+        assert_eq!(key(&close, 3), key(&init, 4));
+        assert_eq!(key(&close, 4), key(&init, 6));
+        assert_eq!(key(&close, 5), key(&init, 9));
+        assert_eq!(key(&close, 6), key(&init, 7));
+
+        let init_with_options = init_with_options(
+            &program_id,
+            &token_program,
+            &initializer,
+            &mint,
+            3,
+            RecipeMode::NonTransferable,
+        );
+        assert_eq!(init_with_options.accounts, init.accounts);
+        assert_eq!(
+            LunabankInstruction::try_from_slice(&init_with_options.data).unwrap(),
+            LunabankInstruction::InitWithOptions {
+                bank_id: 3,
+                recipe_mode: RecipeMode::NonTransferable
+            }
+        );
+    }
+
+    #[test]
+    fn test_asset_accounts() {
+        let program_id = Pubkey::new_unique();
+        let (owner, config, mint) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (user_base, user_recipe) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let token_program = spl_token::id();
+        let (asset, _) = find_asset_address(&program_id, &config, &mint);
+
+        let add_asset = add_asset(&program_id, &token_program, &owner, &config, &mint);
+        assert_eq!(add_asset.accounts.len(), AddAssetAccounts::SPECS.len());
+        assert_eq!(add_asset.accounts[3].pubkey, asset);
+
+        let deposit_asset = deposit_asset(
+            &program_id,
+            &token_program,
+            &owner,
+            &config,
+            &mint,
+            &user_base,
+            &user_recipe,
+            100,
+            10,
+        );
+        assert_eq!(
+            deposit_asset.accounts.len(),
+            DepositAccounts::SPECS.len() + 1
+        );
+        assert_eq!(
+            LunabankInstruction::try_from_slice(&deposit_asset.data).unwrap(),
+            LunabankInstruction::DepositAsset {
+                amount: 100,
+                deposit_time: 10
+            }
+        );
+        let withdraw_asset = withdraw_asset(
+            &program_id,
+            &token_program,
+            &owner,
+            &config,
+            &mint,
+            &user_base,
+            &user_recipe,
+        );
+        assert_eq!(
+            withdraw_asset.accounts.len(),
+            WithdrawAccounts::SPECS.len() + 1
+        );
+
+        let key = |ix: &Instruction, i: usize| ix.accounts[i].pubkey;
+        for ix in [&deposit_asset, &withdraw_asset] {
+            // vault and recipe mint of the asset, authority of the bank
+            assert_eq!(key(ix, 1), config);
+            assert_eq!(key(ix, 3), key(&add_asset, 4));
+            assert_eq!(key(ix, 5), key(&add_asset, 5));
+            assert_eq!(
+                key(ix, 7),
+                find_deposit_address(&program_id, &asset, &owner).0
+            );
+            assert_eq!(key(ix, 8), key(&add_asset, 9));
+            assert_eq!(*ix.accounts.last().unwrap(), AccountMeta::new(asset, false));
+        }
+    }
 }
@@ -1,8 +1,23 @@
+/// `msg!` for diagnostics, only written by a `LoggingProcessor` with `VERBOSE_LOGS`, which
+/// the entrypoint uses with the `verbose-logs` feature. Every log is a syscall charged by the
+/// runtime, the hot paths stay quiet by default.
+macro_rules! verbose_msg {
+    ($($arg:tt)*) => {
+        if VERBOSE_LOGS {
+            solana_program::msg!($($arg)*);
+        }
+    };
+}
+
+pub mod accounts;
 pub mod error;
+pub mod events;
+pub mod filters;
 pub mod instruction;
 pub mod processor;
 pub mod state;
 pub mod utils;
+pub mod zero_copy;
 
 #[cfg(not(feature = "no-entrypoint"))]
 pub mod entrypoint;
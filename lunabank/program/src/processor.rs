@@ -2,34 +2,105 @@ use borsh::BorshDeserialize;
 use solana_program::program::invoke_signed;
 use solana_program::program_option::COption;
 use solana_program::{
-    account_info::{next_account_info, AccountInfo},
+    account_info::AccountInfo,
     entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
     msg,
     program::invoke,
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack},
     pubkey::Pubkey,
-    system_instruction, system_program, sysvar,
+    system_instruction,
     sysvar::{clock::Clock, rent::Rent, Sysvar},
 };
 
-use spl_token::instruction::AuthorityType;
+use spl_token_2022::instruction::AuthorityType;
 
-use spl_token::state::{Account as TokenAccount, Mint};
+use spl_token_2022::{
+    extension::ExtensionType,
+    state::{Account as TokenAccount, Mint},
+};
 
-use crate::state::{Key, LunaDeposit};
+use crate::accounts::{
+    parse_asset, AddAssetAccounts, CloseAccounts, DepositAccounts, FlashLoanAccounts, InitAccounts,
+    MigrateAccounts, RemoveAssetAccounts, ShutdownAccounts, WithdrawAccounts,
+};
+use crate::state::{Key, LunaAsset, LunaDeposit, LunaRegistryEntry, RecipeMode};
 use crate::utils::{
-    assert_account_key, assert_derivation, assert_owned_by, assert_signer, assert_writable,
+    assert_account_key, assert_derivation_with_bump, assert_derived_key, assert_owned_by,
+    assert_token_account, close_program_account, create_pda_account, resize_account, unpack_mint,
+    unpack_token_account, vault_account_len,
 };
 use crate::{
     error::LunabankError,
+    events::{
+        BankClosed, BankInitialized, DepositEvent, FlashLoanEvent, LunabankEvent, WithdrawEvent,
+    },
     instruction::LunabankInstruction,
-    state::{Lunabank, LUNA_DEPOSIT_PREFIX, LUNA_OWNER_PREFIX},
+    state::{
+        asset_seeds, bank_authority_seeds, deposit_seeds, find_asset_address, find_bank_address,
+        find_bank_authority_address, find_deposit_address, find_recipe_mint_address,
+        find_registry_address, find_unwrap_address, find_vault_address, unwrap_seeds, Lunabank,
+        LUNA_BANK_PREFIX, LUNA_RECIPE_PREFIX, LUNA_REGISTRY_PREFIX, LUNA_VAULT_PREFIX,
+    },
+    zero_copy::LunabankView,
 };
 //use mpl_token_metadata::instruction::MetadataInstruction;
 
-pub struct Processor;
-impl Processor {
+/// Base mint, vault and recipe mint a deposit goes through: the ones of the bank, or the
+/// ones of the asset trailing the accounts of `DepositAsset` and `WithdrawAsset`
+struct Pool<'a, 'b> {
+    /// Config or asset account, the deposit and unwrap addresses are derived from it
+    key: Pubkey,
+    base_token_mint: Pubkey,
+    recipe_token_mint: Pubkey,
+    base_token_account: Pubkey,
+    asset: Option<(&'a AccountInfo<'b>, LunaAsset)>,
+}
+
+impl<'a, 'b> Pool<'a, 'b> {
+    fn load(
+        config_info: &AccountInfo,
+        config_data: &Lunabank,
+        asset_info: Option<&'a AccountInfo<'b>>,
+    ) -> Result<Self, ProgramError> {
+        let asset_info = match asset_info {
+            Some(asset_info) => asset_info,
+            None => {
+                return Ok(Self {
+                    key: *config_info.key,
+                    base_token_mint: config_data.base_token_mint,
+                    recipe_token_mint: config_data.recipe_token_mint,
+                    base_token_account: config_data.base_token_account,
+                    asset: None,
+                })
+            }
+        };
+        let asset = LunaAsset::from_account_info(asset_info)?;
+        if asset.bank != *config_info.key {
+            return Err(LunabankError::AssetBankMismatch.into());
+        }
+        Ok(Self {
+            key: *asset_info.key,
+            base_token_mint: asset.base_token_mint,
+            recipe_token_mint: asset.recipe_token_mint,
+            base_token_account: asset.base_token_account,
+            asset: Some((asset_info, asset)),
+        })
+    }
+
+    /// Deposits and withdrawals are made in lamports for the native mint
+    fn is_native(&self) -> bool {
+        self.base_token_mint == spl_token::native_mint::id()
+    }
+}
+
+/// Processor of the entrypoint, with diagnostics when the `verbose-logs` feature is enabled
+pub type Processor = LoggingProcessor<{ cfg!(feature = "verbose-logs") }>;
+
+/// Instruction processor, `VERBOSE_LOGS` turns the `verbose_msg!` diagnostics on
+pub struct LoggingProcessor<const VERBOSE_LOGS: bool>;
+impl<const VERBOSE_LOGS: bool> LoggingProcessor<VERBOSE_LOGS> {
     pub fn process(
         program_id: &Pubkey,
         accounts: &[AccountInfo],
@@ -39,9 +110,9 @@ impl Processor {
         let instruction = LunabankInstruction::try_from_slice(instruction_data)?;
 
         match instruction {
-            LunabankInstruction::Init => {
-                msg!("Instruction: Init");
-                Self::process_init(accounts, program_id)
+            LunabankInstruction::Init { bank_id } => {
+                msg!("Instruction: Init {}", bank_id);
+                Self::process_init(accounts, program_id, bank_id, RecipeMode::Frozen)
             }
             LunabankInstruction::Close => {
                 msg!("Instruction: Close");
@@ -56,258 +127,511 @@ impl Processor {
                     amount,
                     deposit_time
                 );
-                Self::process_deposit(accounts, program_id, amount, deposit_time)
+                Self::process_deposit(accounts, program_id, amount, deposit_time, false)
             }
             LunabankInstruction::WithdrawInstruction => {
                 msg!("Instruction: WithdrawInstruction");
-                Self::process_withdraw(accounts, program_id)
+                Self::process_withdraw(accounts, program_id, false)
+            }
+            LunabankInstruction::Shutdown => {
+                msg!("Instruction: Shutdown");
+                Self::process_shutdown(accounts, program_id)
+            }
+            LunabankInstruction::Migrate => {
+                msg!("Instruction: Migrate");
+                Self::process_migrate(accounts, program_id)
+            }
+            LunabankInstruction::InitWithOptions {
+                bank_id,
+                recipe_mode,
+            } => {
+                msg!("Instruction: InitWithOptions {} {:?}", bank_id, recipe_mode);
+                Self::process_init(accounts, program_id, bank_id, recipe_mode)
+            }
+            LunabankInstruction::AddAsset => {
+                msg!("Instruction: AddAsset");
+                Self::process_add_asset(accounts, program_id)
+            }
+            LunabankInstruction::DepositAsset {
+                amount,
+                deposit_time,
+            } => {
+                msg!("Instruction: DepositAsset {} {}", amount, deposit_time);
+                Self::process_deposit(accounts, program_id, amount, deposit_time, true)
+            }
+            LunabankInstruction::WithdrawAsset => {
+                msg!("Instruction: WithdrawAsset");
+                Self::process_withdraw(accounts, program_id, true)
+            }
+            LunabankInstruction::FlashLoan { amount } => {
+                msg!("Instruction: FlashLoan {}", amount);
+                Self::process_flash_loan(accounts, program_id, amount)
+            }
+            LunabankInstruction::RemoveAsset => {
+                msg!("Instruction: RemoveAsset");
+                Self::process_remove_asset(accounts, program_id)
             }
         }
     }
 
-    fn process_init(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
-        let account_info_iter = &mut accounts.iter();
-        let initializer_info = next_account_info(account_info_iter)?;
-        assert_signer(initializer_info)?;
+    fn process_init(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        bank_id: u64,
+        recipe_mode: RecipeMode,
+    ) -> ProgramResult {
+        let InitAccounts {
+            initializer: initializer_info,
+            config: config_account_info,
+            base_token_mint: base_token_mint_info,
+            base_token_account: base_token_account_info,
+            recipe_token_mint: recipe_token_mint_info,
+            rent_sysvar: sysvar_account_info,
+            token_program: tokenprogram_account_info,
+            registry: registry_account_info,
+            system_program: system_program_info,
+            pda: pda_account_info,
+        } = InitAccounts::parse(program_id, accounts)?;
+
+        let bank_id_bytes = bank_id.to_le_bytes();
+        let config_bump_seed = assert_derived_key(
+            config_account_info,
+            find_bank_address(program_id, base_token_mint_info.key, bank_id),
+        )?;
+        let config_seeds = &[
+            LUNA_BANK_PREFIX.as_bytes(),
+            base_token_mint_info.key.as_ref(),
+            &bank_id_bytes,
+            &[config_bump_seed],
+        ];
 
-        let config_account_info = next_account_info(account_info_iter)?;
-        assert_writable(config_account_info)?;
-        assert_owned_by(config_account_info, program_id)?;
+        let vault_bump_seed = assert_derived_key(
+            base_token_account_info,
+            find_vault_address(program_id, config_account_info.key),
+        )?;
+        let vault_seeds = &[
+            config_account_info.key.as_ref(),
+            LUNA_VAULT_PREFIX.as_bytes(),
+            &[vault_bump_seed],
+        ];
 
-        let base_token_mint_info = next_account_info(account_info_iter)?;
-        assert_owned_by(base_token_mint_info, &spl_token::id())?;
+        let recipe_bump_seed = assert_derived_key(
+            recipe_token_mint_info,
+            find_recipe_mint_address(program_id, config_account_info.key),
+        )?;
+        let recipe_seeds = &[
+            config_account_info.key.as_ref(),
+            LUNA_RECIPE_PREFIX.as_bytes(),
+            &[recipe_bump_seed],
+        ];
 
-        let base_token_account_info = next_account_info(account_info_iter)?;
-        assert_owned_by(base_token_account_info, &spl_token::id())?;
+        let pda_bump_seed = assert_derived_key(
+            pda_account_info,
+            find_bank_authority_address(program_id, config_account_info.key),
+        )?;
 
-        let recipe_token_mint_info = next_account_info(account_info_iter)?;
-        //msg!("recipe_token_mint... {:?}", recipe_token_mint);
-        assert_writable(recipe_token_mint_info)?;
-        assert_owned_by(recipe_token_mint_info, &spl_token::id())?;
+        let registry_bump_seed = assert_derived_key(
+            registry_account_info,
+            find_registry_address(program_id, base_token_mint_info.key, bank_id),
+        )?;
+        let registry_seeds = &[
+            LUNA_REGISTRY_PREFIX.as_bytes(),
+            base_token_mint_info.key.as_ref(),
+            &bank_id_bytes,
+            &[registry_bump_seed],
+        ];
 
-        let sysvar_account_info = next_account_info(account_info_iter)?;
-        assert_account_key(sysvar_account_info, &sysvar::rent::id())?;
+        let rent = &Rent::from_account_info(sysvar_account_info)?;
 
-        let tokenprogram_account_info = next_account_info(account_info_iter)?;
-        assert_account_key(tokenprogram_account_info, &spl_token::id())?;
+        verbose_msg!("Creating config account...");
+        create_pda_account(
+            initializer_info,
+            config_account_info,
+            system_program_info,
+            rent,
+            Lunabank::LEN,
+            program_id,
+            config_seeds,
+        )?;
+        verbose_msg!("config_account: {:?}...", &config_account_info);
 
-        let recipe_token_account_data = Mint::unpack(&recipe_token_mint_info.try_borrow_data()?)?;
-        msg!("recipe_token_mint...{:?}", recipe_token_account_data);
+        Self::create_vault_and_recipe_mint(
+            initializer_info,
+            base_token_mint_info,
+            base_token_account_info,
+            recipe_token_mint_info,
+            pda_account_info,
+            tokenprogram_account_info,
+            system_program_info,
+            sysvar_account_info,
+            recipe_mode,
+            vault_seeds,
+            recipe_seeds,
+        )?;
 
-        let (recipe_token_owner, _) = Pubkey::find_program_address(
-            &[
-                &config_account_info.key.as_ref(),
-                LUNA_OWNER_PREFIX.as_bytes(),
-            ],
+        // setup config
+        let config_data = Lunabank {
+            key: Key::LunabankV2,
+            owner: *initializer_info.key,
+            base_token_mint: *base_token_mint_info.key,
+            recipe_token_mint: *recipe_token_mint_info.key,
+            base_token_account: *base_token_account_info.key,
+            coef: 0,
+            is_shutdown: false,
+            active_deposits: 0,
+            bank_id,
+            pda_bump_seed,
+            recipe_mode,
+            token_program: *tokenprogram_account_info.key,
+            is_locked: false,
+            asset_count: 0,
+            reserved: [0; 22],
+        };
+        verbose_msg!("config: {:?}...", &config_data);
+
+        config_data.save(config_account_info)?;
+
+        verbose_msg!("Creating registry entry...");
+        create_pda_account(
+            initializer_info,
+            registry_account_info,
+            system_program_info,
+            rent,
+            LunaRegistryEntry::LEN,
             program_id,
-        );
-
-        msg!(
-            "pda = {}, cur owner = {:?}",
-            recipe_token_owner,
-            recipe_token_account_data.mint_authority
-        );
-        if recipe_token_account_data.mint_authority != COption::Some(*initializer_info.key) {
-            return Err(ProgramError::IllegalOwner);
+            registry_seeds,
+        )?;
+        LunaRegistryEntry {
+            key: Key::LunaRegistryEntryV1,
+            bank: *config_account_info.key,
+            base_token_mint: *base_token_mint_info.key,
+            bank_id,
         }
+        .save(registry_account_info)?;
 
-        if recipe_token_account_data.freeze_authority != COption::Some(*initializer_info.key) {
-            return Err(ProgramError::IllegalOwner);
-        }
+        LunabankEvent::BankInitialized(BankInitialized {
+            bank: *config_account_info.key,
+            owner: *initializer_info.key,
+            base_token_mint: *base_token_mint_info.key,
+            recipe_token_mint: *recipe_token_mint_info.key,
+            bank_id,
+        })
+        .emit();
 
-        let rent = &Rent::from_account_info(sysvar_account_info)?;
+        Ok(())
+    }
 
-        if !rent.is_exempt(
-            config_account_info.lamports(),
-            config_account_info.data_len(),
-        ) {
-            return Err(LunabankError::NotRentExempt.into());
-        }
-        msg!("config_account: {:?}...", &config_account_info);
+    fn process_add_asset(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let AddAssetAccounts {
+            owner: owner_info,
+            config: config_account_info,
+            base_token_mint: base_token_mint_info,
+            asset: asset_account_info,
+            base_token_account: base_token_account_info,
+            recipe_token_mint: recipe_token_mint_info,
+            rent_sysvar: sysvar_account_info,
+            token_program: tokenprogram_account_info,
+            system_program: system_program_info,
+            pda: pda_account_info,
+        } = AddAssetAccounts::parse(program_id, accounts)?;
 
         let mut config_data = Lunabank::from_account_info(config_account_info)?;
-
-        msg!("unpack done: {:?}...", &config_data);
-        if config_data.is_initialized() {
-            return Err(ProgramError::AccountAlreadyInitialized);
+        if !config_data.is_initialized() {
+            return Err(LunabankError::UninitializedAccount.into());
+        }
+        if config_data.is_locked {
+            return Err(LunabankError::BankLocked.into());
+        }
+        if config_data.owner != *owner_info.key {
+            return Err(LunabankError::BankOwnerMismatch.into());
+        }
+        if config_data.is_shutdown {
+            return Err(LunabankError::BankShutdown.into());
+        }
+        if config_data.token_program != *tokenprogram_account_info.key {
+            return Err(LunabankError::TokenProgramMismatch.into());
         }
 
-        // setup config
-        config_data.key = Key::LunabankV1;
-        config_data.owner = *initializer_info.key;
-        config_data.base_token_mint = *base_token_mint_info.key;
-        config_data.recipe_token_mint = *recipe_token_mint_info.key;
-        config_data.base_token_account = *base_token_account_info.key;
-        config_data.owner = *initializer_info.key;
-
-        Lunabank::pack(config_data, &mut config_account_info.try_borrow_mut_data()?)?;
+        let asset_bump_seed = [assert_derived_key(
+            asset_account_info,
+            find_asset_address(
+                program_id,
+                config_account_info.key,
+                base_token_mint_info.key,
+            ),
+        )?];
+        let asset_seeds = &asset_seeds(
+            config_account_info.key,
+            base_token_mint_info.key,
+            &asset_bump_seed,
+        );
 
-        // transfer authority of reciepe token
-        let transfer_to_pda_ix = spl_token::instruction::set_authority(
-            tokenprogram_account_info.key,
-            recipe_token_mint_info.key,
-            Some(&recipe_token_owner),
-            AuthorityType::MintTokens,
-            initializer_info.key,
-            &[initializer_info.key],
-        )?;
-        msg!("Calling the token program to transfer authority of recipe mint to the bank...");
-        invoke(
-            &transfer_to_pda_ix,
-            &[recipe_token_mint_info.clone(), initializer_info.clone()],
+        let vault_bump_seed = assert_derived_key(
+            base_token_account_info,
+            find_vault_address(program_id, asset_account_info.key),
         )?;
+        let vault_seeds = &[
+            asset_account_info.key.as_ref(),
+            LUNA_VAULT_PREFIX.as_bytes(),
+            &[vault_bump_seed],
+        ];
 
-        let transfer_freeze_to_pda_ix = spl_token::instruction::set_authority(
-            tokenprogram_account_info.key,
-            recipe_token_mint_info.key,
-            Some(&recipe_token_owner),
-            AuthorityType::FreezeAccount,
-            initializer_info.key,
-            &[initializer_info.key],
+        let recipe_bump_seed = assert_derived_key(
+            recipe_token_mint_info,
+            find_recipe_mint_address(program_id, asset_account_info.key),
         )?;
+        let recipe_seeds = &[
+            asset_account_info.key.as_ref(),
+            LUNA_RECIPE_PREFIX.as_bytes(),
+            &[recipe_bump_seed],
+        ];
 
-        msg!("Calling the token program to transfer authority of recipe mint to the bank...");
-        invoke(
-            &transfer_freeze_to_pda_ix,
-            &[recipe_token_mint_info.clone(), initializer_info.clone()],
-        )?;
+        let pda_bump_seed = [config_data.pda_bump_seed];
+        let pda_seeds = &bank_authority_seeds(config_account_info.key, &pda_bump_seed);
+        assert_derivation_with_bump(program_id, pda_account_info, pda_seeds)?;
 
-        let transfer_basetoken_to_pda_ix = spl_token::instruction::set_authority(
-            tokenprogram_account_info.key,
-            base_token_account_info.key,
-            Some(&recipe_token_owner),
-            AuthorityType::AccountOwner,
-            initializer_info.key,
-            &[initializer_info.key],
-        )?;
+        let rent = &Rent::from_account_info(sysvar_account_info)?;
 
-        msg!("Calling the token program to transfer owner of token acc to the bank...");
-        invoke(
-            &transfer_basetoken_to_pda_ix,
-            &[base_token_account_info.clone(), initializer_info.clone()],
+        verbose_msg!("Creating asset account...");
+        create_pda_account(
+            owner_info,
+            asset_account_info,
+            system_program_info,
+            rent,
+            LunaAsset::LEN,
+            program_id,
+            asset_seeds,
         )?;
 
-        //TODO: create & error check
-        Ok(())
-    }
+        Self::create_vault_and_recipe_mint(
+            owner_info,
+            base_token_mint_info,
+            base_token_account_info,
+            recipe_token_mint_info,
+            pda_account_info,
+            tokenprogram_account_info,
+            system_program_info,
+            sysvar_account_info,
+            config_data.recipe_mode,
+            vault_seeds,
+            recipe_seeds,
+        )?;
 
-    fn process_close(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
-        let account_info_iter = &mut accounts.iter();
-        let initializer_info = next_account_info(account_info_iter)?;
-        assert_signer(initializer_info)?;
+        LunaAsset {
+            key: Key::LunaAssetV1,
+            bank: *config_account_info.key,
+            base_token_mint: *base_token_mint_info.key,
+            recipe_token_mint: *recipe_token_mint_info.key,
+            base_token_account: *base_token_account_info.key,
+            active_deposits: 0,
+            bump_seed: asset_bump_seed[0],
+            reserved: [0; 32],
+        }
+        .save(asset_account_info)?;
 
-        let config_account_info = next_account_info(account_info_iter)?;
-        assert_writable(config_account_info)?;
-        assert_owned_by(config_account_info, program_id)?;
+        config_data.asset_count = config_data
+            .asset_count
+            .checked_add(1)
+            .ok_or(LunabankError::AmountOverflow)?;
+        config_data.save(config_account_info)?;
 
-        let base_token_account_info = next_account_info(account_info_iter)?;
-        assert_owned_by(base_token_account_info, &spl_token::id())?;
-        assert_writable(base_token_account_info)?;
+        Ok(())
+    }
 
-        let recipe_token_mint_info = next_account_info(account_info_iter)?;
-        //msg!("recipe_token_mint... {:?}", recipe_token_mint);
-        assert_writable(recipe_token_mint_info)?;
-        assert_owned_by(recipe_token_mint_info, &spl_token::id())?;
+    fn process_remove_asset(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let RemoveAssetAccounts {
+            owner: owner_info,
+            config: config_account_info,
+            asset: asset_account_info,
+            base_token_account: base_token_account_info,
+            recipe_token_mint: recipe_token_mint_info,
+            token_program: tokenprogram_account_info,
+            pda: pda_account_info,
+        } = RemoveAssetAccounts::parse(program_id, accounts)?;
 
-        let tokenprogram_account_info = next_account_info(account_info_iter)?;
-        //msg!("tokenprogram_account... {:?}", tokenprogram_account);
-        assert_account_key(tokenprogram_account_info, &spl_token::id())?;
+        let mut config_data = Lunabank::from_account_info(config_account_info)?;
+        if !config_data.is_initialized() {
+            return Err(LunabankError::UninitializedAccount.into());
+        }
+        if config_data.is_locked {
+            return Err(LunabankError::BankLocked.into());
+        }
+        if config_data.owner != *owner_info.key {
+            return Err(LunabankError::BankOwnerMismatch.into());
+        }
+        if config_data.token_program != *tokenprogram_account_info.key {
+            return Err(LunabankError::TokenProgramMismatch.into());
+        }
 
-        let pda_account_info = next_account_info(account_info_iter)?;
+        let asset_data = LunaAsset::from_account_info(asset_account_info)?;
+        if asset_data.bank != *config_account_info.key {
+            return Err(LunabankError::AssetBankMismatch.into());
+        }
+        if asset_data.base_token_account != *base_token_account_info.key {
+            return Err(LunabankError::VaultMismatch.into());
+        }
+        if asset_data.recipe_token_mint != *recipe_token_mint_info.key {
+            return Err(LunabankError::RecipeMintMismatch.into());
+        }
+        if asset_data.active_deposits != 0 {
+            return Err(LunabankError::DepositsOutstanding.into());
+        }
 
-        let base_token_account_data =
-            TokenAccount::unpack(&base_token_account_info.try_borrow_data()?)?;
+        let pda_bump_seed = [config_data.pda_bump_seed];
+        let pda_seeds = &bank_authority_seeds(config_account_info.key, &pda_bump_seed);
+        assert_derivation_with_bump(program_id, pda_account_info, pda_seeds)?;
 
-        let pda_bump_seed = assert_derivation(
-            program_id,
+        Self::hand_over_vault_and_recipe_mint(
+            owner_info,
+            base_token_account_info,
+            recipe_token_mint_info,
             pda_account_info,
-            &[
-                &config_account_info.key.as_ref(),
-                LUNA_OWNER_PREFIX.as_bytes(),
-            ],
+            tokenprogram_account_info,
+            config_data.recipe_mode,
+            pda_seeds,
         )?;
-        let pda_seeds = &[
-            config_account_info.key.as_ref(),
-            LUNA_OWNER_PREFIX.as_bytes(),
-            &[pda_bump_seed],
-        ];
 
-        //msg!("recipe_token_mint...");
-        let recipetoken_account_data = Mint::unpack(&recipe_token_mint_info.try_borrow_data()?)?;
-        //msg!("recipe_token_mint...{:?}", recipetoken_account_info);
-        msg!(
-            "pda = {}, cur owner = {:?}",
-            pda_account_info.key,
-            recipetoken_account_data.mint_authority
-        );
+        verbose_msg!("Closing asset account...");
+        close_program_account(asset_account_info, owner_info)?;
 
-        if recipetoken_account_data.mint_authority.as_ref() != COption::Some(pda_account_info.key) {
-            return Err(ProgramError::IllegalOwner);
-        }
+        config_data.asset_count = config_data
+            .asset_count
+            .checked_sub(1)
+            .ok_or(LunabankError::AmountOverflow)?;
+        config_data.save(config_account_info)?;
 
-        if recipetoken_account_data.freeze_authority.as_ref() != COption::Some(pda_account_info.key)
-        {
-            return Err(ProgramError::IllegalOwner);
-        }
+        Ok(())
+    }
 
-        if base_token_account_data.owner != *pda_account_info.key {
-            return Err(ProgramError::IllegalOwner);
-        }
+    /// Creates the vault and the recipe mint of a bank or of an asset, both owned by the
+    /// bank authority `pda_account_info`
+    #[allow(clippy::too_many_arguments)]
+    fn create_vault_and_recipe_mint<'a>(
+        payer_info: &AccountInfo<'a>,
+        base_token_mint_info: &AccountInfo<'a>,
+        base_token_account_info: &AccountInfo<'a>,
+        recipe_token_mint_info: &AccountInfo<'a>,
+        pda_account_info: &AccountInfo<'a>,
+        tokenprogram_account_info: &AccountInfo<'a>,
+        system_program_info: &AccountInfo<'a>,
+        sysvar_account_info: &AccountInfo<'a>,
+        recipe_mode: RecipeMode,
+        vault_seeds: &[&[u8]],
+        recipe_seeds: &[&[u8]],
+    ) -> ProgramResult {
+        // banks and assets live in the token program of their base mint
+        assert_owned_by(base_token_mint_info, tokenprogram_account_info.key)?;
+        let base_token_mint_data = unpack_mint(base_token_mint_info)?;
+        let vault_len = vault_account_len(base_token_mint_info)?;
+        let recipe_extensions: &[ExtensionType] = match recipe_mode {
+            RecipeMode::Frozen | RecipeMode::Transferable => &[],
+            RecipeMode::NonTransferable => {
+                if *tokenprogram_account_info.key != spl_token_2022::id() {
+                    return Err(LunabankError::RecipeModeUnsupported.into());
+                }
+                &[ExtensionType::NonTransferable]
+            }
+        };
+        let recipe_len = ExtensionType::try_calculate_account_len::<Mint>(recipe_extensions)?;
 
-        let config_data = Lunabank::from_account_info(config_account_info)?;
-        msg!("unpack config done");
-        //msg!("unpack done: {:?}...", &config_info);
-        if !config_data.is_initialized() {
-            return Err(ProgramError::InvalidAccountData);
-        }
-        if config_data.owner != *initializer_info.key {
-            return Err(ProgramError::IllegalOwner);
-        }
-        if config_data.base_token_mint != base_token_account_data.mint {
-            return Err(ProgramError::InvalidAccountData);
-        }
-        if config_data.recipe_token_mint != *recipe_token_mint_info.key {
-            return Err(ProgramError::InvalidAccountData);
-        }
-        if config_data.base_token_account != *base_token_account_info.key {
-            return Err(ProgramError::InvalidAccountData);
-        }
+        let rent = &Rent::from_account_info(sysvar_account_info)?;
 
-        // transfer authority of reciepe token
-        let transfer_to_initializer_ix = spl_token::instruction::set_authority(
+        verbose_msg!("Creating vault token account...");
+        create_pda_account(
+            payer_info,
+            base_token_account_info,
+            system_program_info,
+            rent,
+            vault_len,
             tokenprogram_account_info.key,
-            recipe_token_mint_info.key,
-            Some(initializer_info.key),
-            AuthorityType::MintTokens,
-            &pda_account_info.key,
-            &[&pda_account_info.key],
+            vault_seeds,
+        )?;
+        invoke(
+            &spl_token_2022::instruction::initialize_account(
+                tokenprogram_account_info.key,
+                base_token_account_info.key,
+                base_token_mint_info.key,
+                pda_account_info.key,
+            )?,
+            &[
+                base_token_account_info.clone(),
+                base_token_mint_info.clone(),
+                pda_account_info.clone(),
+                sysvar_account_info.clone(),
+                tokenprogram_account_info.clone(),
+            ],
         )?;
 
-        msg!("Calling the token program to transfer authority of recipe mint to the bank...");
-
-        //TODO: check add new account inside invoke
-        invoke_signed(
-            &transfer_to_initializer_ix,
+        verbose_msg!("Creating recipe token mint...");
+        create_pda_account(
+            payer_info,
+            recipe_token_mint_info,
+            system_program_info,
+            rent,
+            recipe_len,
+            tokenprogram_account_info.key,
+            recipe_seeds,
+        )?;
+        if recipe_mode == RecipeMode::NonTransferable {
+            invoke(
+                &spl_token_2022::instruction::initialize_non_transferable_mint(
+                    tokenprogram_account_info.key,
+                    recipe_token_mint_info.key,
+                )?,
+                std::slice::from_ref(recipe_token_mint_info),
+            )?;
+        }
+        // the bank authority only needs to freeze recipe accounts in frozen mode
+        let freeze_authority = match recipe_mode {
+            RecipeMode::Frozen => Some(pda_account_info.key),
+            RecipeMode::NonTransferable | RecipeMode::Transferable => None,
+        };
+        invoke(
+            &spl_token_2022::instruction::initialize_mint(
+                tokenprogram_account_info.key,
+                recipe_token_mint_info.key,
+                pda_account_info.key,
+                freeze_authority,
+                base_token_mint_data.decimals,
+            )?,
             &[
                 recipe_token_mint_info.clone(),
-                pda_account_info.clone(),
+                sysvar_account_info.clone(),
                 tokenprogram_account_info.clone(),
             ],
-            &[pda_seeds],
         )?;
 
-        let transfer_freeze_to_initializer_ix = spl_token::instruction::set_authority(
+        Ok(())
+    }
+
+    /// Hands the vault and the recipe mint of a bank or of an asset over from the bank
+    /// authority `pda_account_info` to `owner_info`
+    fn hand_over_vault_and_recipe_mint<'a>(
+        owner_info: &AccountInfo<'a>,
+        base_token_account_info: &AccountInfo<'a>,
+        recipe_token_mint_info: &AccountInfo<'a>,
+        pda_account_info: &AccountInfo<'a>,
+        tokenprogram_account_info: &AccountInfo<'a>,
+        recipe_mode: RecipeMode,
+        pda_seeds: &[&[u8]],
+    ) -> ProgramResult {
+        // transfer authority of reciepe token
+        let transfer_to_initializer_ix = spl_token_2022::instruction::set_authority(
             tokenprogram_account_info.key,
             recipe_token_mint_info.key,
-            Some(initializer_info.key),
-            AuthorityType::FreezeAccount,
-            &pda_account_info.key,
-            &[&pda_account_info.key],
+            Some(owner_info.key),
+            AuthorityType::MintTokens,
+            pda_account_info.key,
+            &[pda_account_info.key],
         )?;
 
-        msg!("Calling the token program to transfer authority of recipe mint to the bank...");
+        verbose_msg!(
+            "Calling the token program to transfer authority of recipe mint to the bank..."
+        );
+
+        //TODO: check add new account inside invoke
         invoke_signed(
-            &transfer_freeze_to_initializer_ix,
+            &transfer_to_initializer_ix,
             &[
                 recipe_token_mint_info.clone(),
                 pda_account_info.clone(),
@@ -316,18 +640,42 @@ impl Processor {
             &[pda_seeds],
         )?;
 
+        if recipe_mode == RecipeMode::Frozen {
+            let transfer_freeze_to_initializer_ix = spl_token_2022::instruction::set_authority(
+                tokenprogram_account_info.key,
+                recipe_token_mint_info.key,
+                Some(owner_info.key),
+                AuthorityType::FreezeAccount,
+                pda_account_info.key,
+                &[pda_account_info.key],
+            )?;
+
+            verbose_msg!(
+                "Calling the token program to transfer authority of recipe mint to the bank..."
+            );
+            invoke_signed(
+                &transfer_freeze_to_initializer_ix,
+                &[
+                    recipe_token_mint_info.clone(),
+                    pda_account_info.clone(),
+                    tokenprogram_account_info.clone(),
+                ],
+                &[pda_seeds],
+            )?;
+        }
+
         // owner of
 
-        let transfer_basetoken_to_initializer_ix = spl_token::instruction::set_authority(
+        let transfer_basetoken_to_initializer_ix = spl_token_2022::instruction::set_authority(
             tokenprogram_account_info.key,
             base_token_account_info.key,
-            Some(initializer_info.key),
+            Some(owner_info.key),
             AuthorityType::AccountOwner,
-            &pda_account_info.key,
-            &[&pda_account_info.key],
+            pda_account_info.key,
+            &[pda_account_info.key],
         )?;
 
-        msg!("Calling the token program to transfer owner of token acc to the bank...");
+        verbose_msg!("Calling the token program to transfer owner of token acc to the bank...");
         invoke_signed(
             &transfer_basetoken_to_initializer_ix,
             &[
@@ -338,129 +686,228 @@ impl Processor {
             &[pda_seeds],
         )?;
 
-        msg!("Closing config account...");
-        **initializer_info.try_borrow_mut_lamports()? = initializer_info
-            .lamports()
-            .checked_add(config_account_info.lamports())
-            .ok_or(LunabankError::AmountOverflow)?;
-        **config_account_info.try_borrow_mut_lamports()? = 0;
-        *config_account_info.try_borrow_mut_data()? = &mut [];
-
         Ok(())
     }
 
-    fn process_deposit(
-        accounts: &[AccountInfo],
-        program_id: &Pubkey,
-        amount: u64,
-        deposit_time: u64,
-    ) -> ProgramResult {
-        let account_info_iter = &mut accounts.iter();
-        let payer_info = next_account_info(account_info_iter)?;
-        assert_signer(payer_info)?;
+    fn process_close(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let CloseAccounts {
+            initializer: initializer_info,
+            config: config_account_info,
+            base_token_account: base_token_account_info,
+            recipe_token_mint: recipe_token_mint_info,
+            token_program: tokenprogram_account_info,
+            pda: pda_account_info,
+            registry: registry_account_info,
+        } = CloseAccounts::parse(program_id, accounts)?;
+
+        let base_token_account_data = unpack_token_account(base_token_account_info)?;
 
-        let config_account_info = next_account_info(account_info_iter)?;
-        assert_owned_by(config_account_info, program_id)?;
+        let config_data = Lunabank::from_account_info(config_account_info)?;
+        verbose_msg!("unpack config done");
+        //msg!("unpack done: {:?}...", &config_info);
+        if !config_data.is_initialized() {
+            return Err(LunabankError::UninitializedAccount.into());
+        }
+        if config_data.is_locked {
+            return Err(LunabankError::BankLocked.into());
+        }
+
+        let pda_bump_seed = [config_data.pda_bump_seed];
+        let pda_seeds = &bank_authority_seeds(config_account_info.key, &pda_bump_seed);
+        assert_derivation_with_bump(program_id, pda_account_info, pda_seeds)?;
+        assert_derived_key(
+            registry_account_info,
+            find_registry_address(
+                program_id,
+                &config_data.base_token_mint,
+                config_data.bank_id,
+            ),
+        )?;
 
-        let base_token_mint_info = next_account_info(account_info_iter)?;
-        assert_owned_by(base_token_mint_info, &spl_token::id())?;
+        //msg!("recipe_token_mint...");
+        let recipetoken_account_data = unpack_mint(recipe_token_mint_info)?;
+        //msg!("recipe_token_mint...{:?}", recipetoken_account_info);
+        verbose_msg!(
+            "pda = {}, cur owner = {:?}",
+            pda_account_info.key,
+            recipetoken_account_data.mint_authority
+        );
 
-        let base_token_account_info = next_account_info(account_info_iter)?;
-        assert_owned_by(base_token_account_info, &spl_token::id())?;
-        assert_writable(base_token_account_info)?;
+        if recipetoken_account_data.mint_authority.as_ref() != COption::Some(pda_account_info.key) {
+            return Err(LunabankError::BankAuthorityMismatch.into());
+        }
 
-        let user_base_token_account_info = next_account_info(account_info_iter)?;
-        assert_owned_by(user_base_token_account_info, &spl_token::id())?;
-        assert_writable(user_base_token_account_info)?;
+        if config_data.recipe_mode == RecipeMode::Frozen
+            && recipetoken_account_data.freeze_authority.as_ref()
+                != COption::Some(pda_account_info.key)
+        {
+            return Err(LunabankError::BankAuthorityMismatch.into());
+        }
 
-        let recipe_token_mint_info = next_account_info(account_info_iter)?;
-        //msg!("recipe_token_mint... {:?}", recipe_token_mint);
-        assert_owned_by(recipe_token_mint_info, &spl_token::id())?;
-        assert_writable(recipe_token_mint_info)?;
+        if base_token_account_data.owner != *pda_account_info.key {
+            return Err(LunabankError::BankAuthorityMismatch.into());
+        }
 
-        let user_recipe_token_account_info = next_account_info(account_info_iter)?;
-        assert_owned_by(user_recipe_token_account_info, &spl_token::id())?;
-        assert_writable(user_recipe_token_account_info)?;
+        if config_data.owner != *initializer_info.key {
+            return Err(LunabankError::BankOwnerMismatch.into());
+        }
+        if config_data.base_token_mint != base_token_account_data.mint {
+            return Err(LunabankError::BaseMintMismatch.into());
+        }
+        if config_data.recipe_token_mint != *recipe_token_mint_info.key {
+            return Err(LunabankError::RecipeMintMismatch.into());
+        }
+        if config_data.base_token_account != *base_token_account_info.key {
+            return Err(LunabankError::VaultMismatch.into());
+        }
+        if config_data.token_program != *tokenprogram_account_info.key {
+            return Err(LunabankError::TokenProgramMismatch.into());
+        }
+        // deposits of a V1 bank are counted as they are migrated, those still in the V1
+        // layout only show in the recipe supply
+        if config_data.active_deposits != 0 || recipetoken_account_data.supply != 0 {
+            return Err(LunabankError::DepositsOutstanding.into());
+        }
+        if config_data.asset_count != 0 {
+            return Err(LunabankError::AssetsOutstanding.into());
+        }
 
-        let pda_user_account_info = next_account_info(account_info_iter)?;
-        assert_writable(pda_user_account_info)?;
+        Self::hand_over_vault_and_recipe_mint(
+            initializer_info,
+            base_token_account_info,
+            recipe_token_mint_info,
+            pda_account_info,
+            tokenprogram_account_info,
+            config_data.recipe_mode,
+            pda_seeds,
+        )?;
 
-        let pda_account_info = next_account_info(account_info_iter)?;
+        // banks with a keypair config account, from before the registry, have no entry, and
+        // the address of theirs may belong to the bank of the same mint and bank_id
+        if registry_account_info.owner == program_id
+            && LunaRegistryEntry::from_account_info(registry_account_info)?.bank
+                == *config_account_info.key
+        {
+            verbose_msg!("Closing registry entry...");
+            close_program_account(registry_account_info, initializer_info)?;
+        }
 
-        let tokenprogram_account_info = next_account_info(account_info_iter)?;
-        assert_account_key(tokenprogram_account_info, &spl_token::id())?;
+        verbose_msg!("Closing config account...");
+        close_program_account(config_account_info, initializer_info)?;
 
-        let sysvar_account_info = next_account_info(account_info_iter)?;
-        assert_account_key(sysvar_account_info, &sysvar::rent::id())?;
+        LunabankEvent::BankClosed(BankClosed {
+            bank: *config_account_info.key,
+            owner: *initializer_info.key,
+        })
+        .emit();
 
-        let system_program_info = next_account_info(account_info_iter)?;
-        assert_account_key(system_program_info, &system_program::id())?;
+        Ok(())
+    }
 
-        let clock_sysvar_info = next_account_info(account_info_iter)?;
-        assert_account_key(clock_sysvar_info, &sysvar::clock::id())?;
+    fn process_deposit(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        amount: u64,
+        deposit_time: u64,
+        with_asset: bool,
+    ) -> ProgramResult {
+        let DepositAccounts {
+            payer: payer_info,
+            config: config_account_info,
+            base_token_mint: base_token_mint_info,
+            base_token_account: base_token_account_info,
+            user_base_token_account: user_base_token_account_info,
+            recipe_token_mint: recipe_token_mint_info,
+            user_recipe_token_account: user_recipe_token_account_info,
+            deposit: pda_user_account_info,
+            pda: pda_account_info,
+            token_program: tokenprogram_account_info,
+            rent_sysvar: sysvar_account_info,
+            system_program: system_program_info,
+            clock_sysvar: clock_sysvar_info,
+        } = DepositAccounts::parse(program_id, accounts)?;
+        let asset_info = parse_asset(
+            program_id,
+            accounts,
+            DepositAccounts::SPECS.len(),
+            with_asset,
+        )?;
 
-        msg!("accounts.. ok");
-        let base_token_account_data =
-            TokenAccount::unpack(&base_token_account_info.try_borrow_data()?)?;
-        let recipe_token_mint_data = Mint::unpack(&recipe_token_mint_info.try_borrow_data()?)?;
-        let config_data = Lunabank::from_account_info(config_account_info)?;
+        verbose_msg!("accounts.. ok");
+        let base_token_account_data = unpack_token_account(base_token_account_info)?;
+        let recipe_token_mint_data = unpack_mint(recipe_token_mint_info)?;
+        let mut config_data = Lunabank::from_account_info(config_account_info)?;
 
-        msg!("unpack config done");
+        verbose_msg!("unpack config done");
         //msg!("unpack done: {:?}...", &config_info);
         if !config_data.is_initialized() {
-            return Err(ProgramError::InvalidAccountData);
+            return Err(LunabankError::UninitializedAccount.into());
         }
-        if config_data.base_token_mint != base_token_account_data.mint {
-            return Err(ProgramError::InvalidAccountData);
+        if config_data.is_locked {
+            return Err(LunabankError::BankLocked.into());
         }
-        if config_data.recipe_token_mint != *recipe_token_mint_info.key {
-            return Err(ProgramError::InvalidAccountData);
+        if config_data.is_shutdown {
+            return Err(LunabankError::BankShutdown.into());
         }
-        if config_data.base_token_account != *base_token_account_info.key {
-            return Err(ProgramError::InvalidAccountData);
+        let mut pool = Pool::load(config_account_info, &config_data, asset_info)?;
+        if pool.base_token_mint != *base_token_mint_info.key {
+            return Err(LunabankError::BaseMintMismatch.into());
         }
+        if pool.base_token_mint != base_token_account_data.mint {
+            return Err(LunabankError::BaseMintMismatch.into());
+        }
+        if pool.recipe_token_mint != *recipe_token_mint_info.key {
+            return Err(LunabankError::RecipeMintMismatch.into());
+        }
+        if pool.base_token_account != *base_token_account_info.key {
+            return Err(LunabankError::VaultMismatch.into());
+        }
+        if config_data.token_program != *tokenprogram_account_info.key {
+            return Err(LunabankError::TokenProgramMismatch.into());
+        }
+        let base_token_mint_data = unpack_mint(base_token_mint_info)?;
 
-        let pda_deposit_bump_seed = assert_derivation(
-            program_id,
+        let pda_deposit_bump_seed = [assert_derived_key(
             pda_user_account_info,
-            &[
-                &config_account_info.key.as_ref(),
-                payer_info.key.as_ref(),
-                LUNA_DEPOSIT_PREFIX.as_bytes(),
-            ],
-        )?;
-        let pda_deposit_seeds = &[
-            config_account_info.key.as_ref(),
-            payer_info.key.as_ref(),
-            LUNA_DEPOSIT_PREFIX.as_bytes(),
-            &[pda_deposit_bump_seed],
-        ];
+            find_deposit_address(program_id, &pool.key, payer_info.key),
+        )?];
+        let pda_deposit_seeds = &deposit_seeds(&pool.key, payer_info.key, &pda_deposit_bump_seed);
 
-        let pda_bump_seed = assert_derivation(
-            program_id,
-            pda_account_info,
-            &[
-                &config_account_info.key.as_ref(),
-                LUNA_OWNER_PREFIX.as_bytes(),
-            ],
-        )?;
-        let pda_seeds = &[
-            config_account_info.key.as_ref(),
-            LUNA_OWNER_PREFIX.as_bytes(),
-            &[pda_bump_seed],
-        ];
+        let pda_bump_seed = [config_data.pda_bump_seed];
+        let pda_seeds = &bank_authority_seeds(config_account_info.key, &pda_bump_seed);
+        assert_derivation_with_bump(program_id, pda_account_info, pda_seeds)?;
 
         if recipe_token_mint_data.mint_authority != COption::Some(*pda_account_info.key) {
-            return Err(ProgramError::IllegalOwner);
+            return Err(LunabankError::BankAuthorityMismatch.into());
         }
 
-        if recipe_token_mint_data.freeze_authority != COption::Some(*pda_account_info.key) {
-            return Err(ProgramError::IllegalOwner);
+        if config_data.recipe_mode == RecipeMode::Frozen
+            && recipe_token_mint_data.freeze_authority != COption::Some(*pda_account_info.key)
+        {
+            return Err(LunabankError::BankAuthorityMismatch.into());
         }
 
-        if base_token_account_data.owner != *pda_account_info.key {
-            return Err(ProgramError::IllegalOwner);
+        assert_token_account(
+            &base_token_account_data,
+            &pool.base_token_mint,
+            pda_account_info.key,
+        )?;
+        if pool.is_native() {
+            // lamports are taken from the payer directly
+            assert_account_key(user_base_token_account_info, payer_info.key)?;
+        } else {
+            assert_owned_by(user_base_token_account_info, tokenprogram_account_info.key)?;
+            assert_token_account(
+                &unpack_token_account(user_base_token_account_info)?,
+                &pool.base_token_mint,
+                payer_info.key,
+            )?;
         }
+        assert_token_account(
+            &unpack_token_account(user_recipe_token_account_info)?,
+            &pool.recipe_token_mint,
+            payer_info.key,
+        )?;
 
         // ///////////
         // // end of checks
@@ -485,64 +932,106 @@ impl Processor {
             &[&pda_deposit_seeds[..]],
         )?;
 
-        let mut deposit_acc_data =
-            LunaDeposit::unpack_unchecked(&pda_user_account_info.try_borrow_data()?)?;
-        msg!("unpack done: {:?}...", &deposit_acc_data);
-        if deposit_acc_data.is_initialized() {
-            return Err(ProgramError::AccountAlreadyInitialized);
-        }
-        let clock = Clock::from_account_info(&clock_sysvar_info)?;
+        let clock = Clock::from_account_info(clock_sysvar_info)?;
         let cur_ts = clock.unix_timestamp as u64;
         // setup config
-        deposit_acc_data.key = Key::LunaDepositV1;
-        deposit_acc_data.owner = *payer_info.key;
-        deposit_acc_data.amount = amount;
-        deposit_acc_data.start_timestamp = cur_ts;
-        deposit_acc_data.end_timestamp = cur_ts
-            .checked_add(deposit_time)
-            .ok_or(LunabankError::TimeOverflow)?;
-        msg!("unpack done: {:?}...", &deposit_acc_data);
-        LunaDeposit::pack(
-            deposit_acc_data,
-            &mut pda_user_account_info.try_borrow_mut_data()?,
-        )?;
+        let deposit_acc_data = LunaDeposit {
+            key: Key::LunaDepositV3,
+            owner: *payer_info.key,
+            amount,
+            start_timestamp: cur_ts,
+            end_timestamp: cur_ts
+                .checked_add(deposit_time)
+                .ok_or(LunabankError::TimeOverflow)?,
+            bump_seed: pda_deposit_bump_seed[0],
+            bank: *config_account_info.key,
+            base_token_mint: pool.base_token_mint,
+            reserved: [0; 32],
+        };
+        verbose_msg!("unpack done: {:?}...", &deposit_acc_data);
+        let event = LunabankEvent::Deposit(DepositEvent {
+            bank: *config_account_info.key,
+            owner: *payer_info.key,
+            amount,
+            start_timestamp: deposit_acc_data.start_timestamp,
+            end_timestamp: deposit_acc_data.end_timestamp,
+        });
+        deposit_acc_data.save(pda_user_account_info)?;
+
+        config_data.active_deposits = config_data
+            .active_deposits
+            .checked_add(1)
+            .ok_or(LunabankError::AmountOverflow)?;
+        config_data.save(config_account_info)?;
+        if let Some((asset_info, asset)) = &mut pool.asset {
+            asset.active_deposits = asset
+                .active_deposits
+                .checked_add(1)
+                .ok_or(LunabankError::AmountOverflow)?;
+            asset.save(asset_info)?;
+        }
 
         // transfer base tokens
         //let transfer_basetoken_to_pda_ix = ;
 
-        msg!("Calling the token program to transfer owner of token acc to the bank...{} -> {}, pay: {}",
+        verbose_msg!("Calling the token program to transfer owner of token acc to the bank...{} -> {}, pay: {}",
             user_base_token_account_info.key,
             base_token_account_info.key,
             payer_info.key,
         );
-        invoke(
-            &spl_token::instruction::transfer(
-                tokenprogram_account_info.key,
-                user_base_token_account_info.key,
-                base_token_account_info.key,
-                payer_info.key,
-                &[payer_info.key],
-                amount,
-            )?,
-            &[
-                user_base_token_account_info.clone(),
-                base_token_account_info.clone(),
-                payer_info.clone(),
-            ],
-        )?;
+        if pool.is_native() {
+            // wrap: fund the vault, then have the token program count the lamports in
+            invoke(
+                &system_instruction::transfer(payer_info.key, base_token_account_info.key, amount),
+                &[
+                    payer_info.clone(),
+                    base_token_account_info.clone(),
+                    system_program_info.clone(),
+                ],
+            )?;
+            invoke(
+                &spl_token_2022::instruction::sync_native(
+                    tokenprogram_account_info.key,
+                    base_token_account_info.key,
+                )?,
+                &[
+                    base_token_account_info.clone(),
+                    tokenprogram_account_info.clone(),
+                ],
+            )?;
+        } else {
+            invoke(
+                &spl_token_2022::instruction::transfer_checked(
+                    tokenprogram_account_info.key,
+                    user_base_token_account_info.key,
+                    base_token_mint_info.key,
+                    base_token_account_info.key,
+                    payer_info.key,
+                    &[payer_info.key],
+                    amount,
+                    base_token_mint_data.decimals,
+                )?,
+                &[
+                    user_base_token_account_info.clone(),
+                    base_token_mint_info.clone(),
+                    base_token_account_info.clone(),
+                    payer_info.clone(),
+                ],
+            )?;
+        }
 
-        msg!(
+        verbose_msg!(
             "mint_to... {} {} {}",
             recipe_token_mint_info.key,
             recipe_token_mint_info.key,
             user_recipe_token_account_info.key
         );
         invoke_signed(
-            &spl_token::instruction::mint_to(
+            &spl_token_2022::instruction::mint_to(
                 tokenprogram_account_info.key,
                 recipe_token_mint_info.key,
                 user_recipe_token_account_info.key,
-                &pda_account_info.key,
+                pda_account_info.key,
                 &[pda_account_info.key],
                 amount,
             )?,
@@ -555,199 +1044,267 @@ impl Processor {
             &[pda_seeds],
         )?;
 
-        invoke_signed(
-            &spl_token::instruction::freeze_account(
-                tokenprogram_account_info.key,
-                user_recipe_token_account_info.key,
-                recipe_token_mint_info.key,
-                &pda_account_info.key,
-                &[pda_account_info.key],
-            )?,
-            &[
-                user_recipe_token_account_info.clone(),
-                recipe_token_mint_info.clone(),
-                pda_account_info.clone(),
-                tokenprogram_account_info.clone(),
-            ],
-            &[pda_seeds],
-        )?;
+        if config_data.recipe_mode == RecipeMode::Frozen {
+            invoke_signed(
+                &spl_token_2022::instruction::freeze_account(
+                    tokenprogram_account_info.key,
+                    user_recipe_token_account_info.key,
+                    recipe_token_mint_info.key,
+                    pda_account_info.key,
+                    &[pda_account_info.key],
+                )?,
+                &[
+                    user_recipe_token_account_info.clone(),
+                    recipe_token_mint_info.clone(),
+                    pda_account_info.clone(),
+                    tokenprogram_account_info.clone(),
+                ],
+                &[pda_seeds],
+            )?;
+        }
         //
 
+        event.emit();
+
         Ok(())
     }
 
-    fn process_withdraw(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
-        let account_info_iter = &mut accounts.iter();
-        let payer_info = next_account_info(account_info_iter)?;
-        assert_signer(payer_info)?;
-
-        let config_account_info = next_account_info(account_info_iter)?;
-        assert_owned_by(config_account_info, program_id)?;
-
-        let base_token_mint_info = next_account_info(account_info_iter)?;
-        assert_owned_by(base_token_mint_info, &spl_token::id())?;
-
-        let base_token_account_info = next_account_info(account_info_iter)?;
-        assert_owned_by(base_token_account_info, &spl_token::id())?;
-        assert_writable(base_token_account_info)?;
-
-        let user_base_token_account_info = next_account_info(account_info_iter)?;
-        assert_owned_by(user_base_token_account_info, &spl_token::id())?;
-        assert_writable(user_base_token_account_info)?;
-
-        let recipe_token_mint_info = next_account_info(account_info_iter)?;
-        assert_owned_by(recipe_token_mint_info, &spl_token::id())?;
-        assert_writable(recipe_token_mint_info)?;
-
-        let user_recipe_token_account_info = next_account_info(account_info_iter)?;
-        assert_owned_by(user_recipe_token_account_info, &spl_token::id())?;
-        assert_writable(user_recipe_token_account_info)?;
-
-        let pda_user_account_info = next_account_info(account_info_iter)?;
-        assert_writable(pda_user_account_info)?;
-
-        let pda_account_info = next_account_info(account_info_iter)?;
-
-        let tokenprogram_account_info = next_account_info(account_info_iter)?;
-        assert_account_key(tokenprogram_account_info, &spl_token::id())?;
-
-        let system_program_info = next_account_info(account_info_iter)?;
-        assert_account_key(system_program_info, &system_program::id())?;
-
-        let clock_sysvar_info = next_account_info(account_info_iter)?;
-        assert_account_key(clock_sysvar_info, &sysvar::clock::id())?;
+    fn process_withdraw(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        with_asset: bool,
+    ) -> ProgramResult {
+        let WithdrawAccounts {
+            payer: payer_info,
+            config: config_account_info,
+            base_token_mint: base_token_mint_info,
+            base_token_account: base_token_account_info,
+            user_base_token_account: user_base_token_account_info,
+            recipe_token_mint: recipe_token_mint_info,
+            user_recipe_token_account: user_recipe_token_account_info,
+            deposit: pda_user_account_info,
+            pda: pda_account_info,
+            token_program: tokenprogram_account_info,
+            system_program: system_program_info,
+            clock_sysvar: clock_sysvar_info,
+        } = WithdrawAccounts::parse(program_id, accounts)?;
+        let asset_info = parse_asset(
+            program_id,
+            accounts,
+            WithdrawAccounts::SPECS.len(),
+            with_asset,
+        )?;
 
-        msg!("accounts.. ok");
-        let base_token_account_data =
-            TokenAccount::unpack(&base_token_account_info.try_borrow_data()?)?;
-        let recipe_token_account_data = Mint::unpack(&recipe_token_mint_info.try_borrow_data()?)?;
-        let config_data = Lunabank::from_account_info(config_account_info)?;
+        verbose_msg!("accounts.. ok");
+        let base_token_account_data = unpack_token_account(base_token_account_info)?;
+        let recipe_token_account_data = unpack_mint(recipe_token_mint_info)?;
+        let mut config_data = Lunabank::from_account_info(config_account_info)?;
 
-        msg!("unpack config done");
+        verbose_msg!("unpack config done");
         //msg!("unpack done: {:?}...", &config_info);
         if !config_data.is_initialized() {
-            return Err(ProgramError::InvalidAccountData);
+            return Err(LunabankError::UninitializedAccount.into());
         }
+        if config_data.is_locked {
+            return Err(LunabankError::BankLocked.into());
+        }
+        let mut pool = Pool::load(config_account_info, &config_data, asset_info)?;
 
-        if config_data.base_token_mint != base_token_account_data.mint {
-            return Err(ProgramError::InvalidAccountData);
+        if pool.base_token_mint != *base_token_mint_info.key {
+            return Err(LunabankError::BaseMintMismatch.into());
         }
-        if config_data.recipe_token_mint != *recipe_token_mint_info.key {
-            return Err(ProgramError::InvalidAccountData);
+        if pool.base_token_mint != base_token_account_data.mint {
+            return Err(LunabankError::BaseMintMismatch.into());
         }
-        if config_data.base_token_account != *base_token_account_info.key {
-            return Err(ProgramError::InvalidAccountData);
+        if pool.recipe_token_mint != *recipe_token_mint_info.key {
+            return Err(LunabankError::RecipeMintMismatch.into());
+        }
+        if pool.base_token_account != *base_token_account_info.key {
+            return Err(LunabankError::VaultMismatch.into());
         }
+        if config_data.token_program != *tokenprogram_account_info.key {
+            return Err(LunabankError::TokenProgramMismatch.into());
+        }
+        let base_token_mint_data = unpack_mint(base_token_mint_info)?;
 
-        assert_derivation(
+        let deposit_acc_data = LunaDeposit::from_account_info(pda_user_account_info)?;
+        //msg!("unpack done: {:?}...", &deposit_acc_info);
+        if !deposit_acc_data.is_initialized() {
+            return Err(LunabankError::UninitializedAccount.into());
+        }
+        if deposit_acc_data.bank != *config_account_info.key {
+            return Err(LunabankError::DepositBankMismatch.into());
+        }
+        assert_derivation_with_bump(
             program_id,
             pda_user_account_info,
-            &[
-                &config_account_info.key.as_ref(),
-                payer_info.key.as_ref(),
-                LUNA_DEPOSIT_PREFIX.as_bytes(),
-            ],
+            &deposit_seeds(&pool.key, payer_info.key, &[deposit_acc_data.bump_seed]),
         )?;
 
-        let pda_bump_seed = assert_derivation(
-            program_id,
-            pda_account_info,
-            &[
-                &config_account_info.key.as_ref(),
-                LUNA_OWNER_PREFIX.as_bytes(),
-            ],
-        )?;
-        let pda_seeds = &[
-            config_account_info.key.as_ref(),
-            LUNA_OWNER_PREFIX.as_bytes(),
-            &[pda_bump_seed],
-        ];
+        let pda_bump_seed = [config_data.pda_bump_seed];
+        let pda_seeds = &bank_authority_seeds(config_account_info.key, &pda_bump_seed);
+        assert_derivation_with_bump(program_id, pda_account_info, pda_seeds)?;
 
         if recipe_token_account_data.mint_authority.as_ref() != COption::Some(pda_account_info.key)
         {
-            return Err(ProgramError::IllegalOwner);
+            return Err(LunabankError::BankAuthorityMismatch.into());
         }
 
-        if recipe_token_account_data.freeze_authority.as_ref()
-            != COption::Some(pda_account_info.key)
+        if config_data.recipe_mode == RecipeMode::Frozen
+            && recipe_token_account_data.freeze_authority.as_ref()
+                != COption::Some(pda_account_info.key)
         {
-            return Err(ProgramError::IllegalOwner);
+            return Err(LunabankError::BankAuthorityMismatch.into());
         }
 
-        if base_token_account_data.owner != *pda_account_info.key {
-            return Err(ProgramError::IllegalOwner);
+        assert_token_account(
+            &base_token_account_data,
+            &pool.base_token_mint,
+            pda_account_info.key,
+        )?;
+        let unwrap_bump_seed = if pool.is_native() {
+            // lamports go back through a wrapped SOL account created for the withdrawal
+            Some(assert_derived_key(
+                user_base_token_account_info,
+                find_unwrap_address(program_id, &pool.key, payer_info.key),
+            )?)
+        } else {
+            assert_owned_by(user_base_token_account_info, tokenprogram_account_info.key)?;
+            assert_token_account(
+                &unpack_token_account(user_base_token_account_info)?,
+                &pool.base_token_mint,
+                payer_info.key,
+            )?;
+            None
+        };
+        let user_recipe_token_account_data = unpack_token_account(user_recipe_token_account_info)?;
+        if config_data.recipe_mode == RecipeMode::Transferable {
+            // the burn below fails unless the payer owns the account or is its delegate
+            if user_recipe_token_account_data.mint != pool.recipe_token_mint {
+                return Err(LunabankError::TokenMintMismatch.into());
+            }
+        } else {
+            assert_token_account(
+                &user_recipe_token_account_data,
+                &pool.recipe_token_mint,
+                payer_info.key,
+            )?;
         }
 
         // ///////////
         // // end of checks
         // //////////
 
-        let deposit_acc_data =
-            LunaDeposit::unpack_unchecked(&pda_user_account_info.try_borrow_data()?)?;
-        //msg!("unpack done: {:?}...", &deposit_acc_info);
-        if !deposit_acc_data.is_initialized() {
-            return Err(ProgramError::AccountAlreadyInitialized);
-        }
-        let clock = Clock::from_account_info(&clock_sysvar_info)?;
+        let clock = Clock::from_account_info(clock_sysvar_info)?;
         let cur_ts = clock.unix_timestamp as u64;
-        if cur_ts < deposit_acc_data.end_timestamp {
-            return Err(LunabankError::TimeOverflow.into());
+        // once the bank is shut down depositors may leave without waiting
+        if !config_data.is_shutdown && cur_ts < deposit_acc_data.end_timestamp {
+            return Err(LunabankError::WaitPeriodBreach.into());
         }
         // setup config
         let amount = deposit_acc_data.amount;
         if deposit_acc_data.owner != *payer_info.key {
-            return Err(ProgramError::IllegalOwner);
+            return Err(LunabankError::DepositOwnerMismatch.into());
         }
 
         // transfer base tokens
         //let transfer_basetoken_to_pda_ix = ;
 
-        msg!("Calling the token program to transfer owner of token acc to the bank...{} -> {}, pay: {}",
+        verbose_msg!("Calling the token program to transfer owner of token acc to the bank...{} -> {}, pay: {}",
             user_base_token_account_info.key,
             base_token_account_info.key,
             payer_info.key,
         );
+        if let Some(unwrap_bump_seed) = unwrap_bump_seed {
+            let unwrap_bump_seed = [unwrap_bump_seed];
+            create_pda_account(
+                payer_info,
+                user_base_token_account_info,
+                system_program_info,
+                &Rent::get()?,
+                TokenAccount::LEN,
+                tokenprogram_account_info.key,
+                &unwrap_seeds(&pool.key, payer_info.key, &unwrap_bump_seed),
+            )?;
+            invoke(
+                &spl_token_2022::instruction::initialize_account3(
+                    tokenprogram_account_info.key,
+                    user_base_token_account_info.key,
+                    base_token_mint_info.key,
+                    pda_account_info.key,
+                )?,
+                &[
+                    user_base_token_account_info.clone(),
+                    base_token_mint_info.clone(),
+                    tokenprogram_account_info.clone(),
+                ],
+            )?;
+        }
         invoke_signed(
-            &spl_token::instruction::transfer(
+            &spl_token_2022::instruction::transfer_checked(
                 tokenprogram_account_info.key,
                 base_token_account_info.key,
+                base_token_mint_info.key,
                 user_base_token_account_info.key,
                 pda_account_info.key,
                 &[pda_account_info.key],
                 amount,
+                base_token_mint_data.decimals,
             )?,
             &[
                 user_base_token_account_info.clone(),
+                base_token_mint_info.clone(),
                 base_token_account_info.clone(),
                 pda_account_info.clone(),
                 tokenprogram_account_info.clone(),
             ],
             &[pda_seeds],
         )?;
+        if unwrap_bump_seed.is_some() {
+            // unwrap: closing the account hands its lamports, rent included, to the payer
+            invoke_signed(
+                &spl_token_2022::instruction::close_account(
+                    tokenprogram_account_info.key,
+                    user_base_token_account_info.key,
+                    payer_info.key,
+                    pda_account_info.key,
+                    &[pda_account_info.key],
+                )?,
+                &[
+                    user_base_token_account_info.clone(),
+                    payer_info.clone(),
+                    pda_account_info.clone(),
+                    tokenprogram_account_info.clone(),
+                ],
+                &[pda_seeds],
+            )?;
+        }
 
-        invoke_signed(
-            &spl_token::instruction::thaw_account(
-                tokenprogram_account_info.key,
-                user_recipe_token_account_info.key,
-                recipe_token_mint_info.key,
-                &pda_account_info.key,
-                &[pda_account_info.key],
-            )?,
-            &[
-                user_recipe_token_account_info.clone(),
-                recipe_token_mint_info.clone(),
-                pda_account_info.clone(),
-                tokenprogram_account_info.clone(),
-            ],
-            &[pda_seeds],
-        )?;
-        msg!("burn {}", user_recipe_token_account_info.key);
+        if config_data.recipe_mode == RecipeMode::Frozen {
+            invoke_signed(
+                &spl_token_2022::instruction::thaw_account(
+                    tokenprogram_account_info.key,
+                    user_recipe_token_account_info.key,
+                    recipe_token_mint_info.key,
+                    pda_account_info.key,
+                    &[pda_account_info.key],
+                )?,
+                &[
+                    user_recipe_token_account_info.clone(),
+                    recipe_token_mint_info.clone(),
+                    pda_account_info.clone(),
+                    tokenprogram_account_info.clone(),
+                ],
+                &[pda_seeds],
+            )?;
+        }
+        verbose_msg!("burn {}", user_recipe_token_account_info.key);
         invoke(
-            &spl_token::instruction::burn(
+            &spl_token_2022::instruction::burn(
                 tokenprogram_account_info.key,
                 user_recipe_token_account_info.key,
                 recipe_token_mint_info.key,
-                &payer_info.key,
+                payer_info.key,
                 &[payer_info.key],
                 amount,
             )?,
@@ -759,22 +1316,25 @@ impl Processor {
             ],
         )?;
 
-        msg!("close {}", user_recipe_token_account_info.key);
-        invoke(
-            &spl_token::instruction::close_account(
-                tokenprogram_account_info.key,
-                user_recipe_token_account_info.key,
-                payer_info.key,
-                &payer_info.key,
-                &[payer_info.key],
-            )?,
-            &[
-                user_recipe_token_account_info.clone(),
-                payer_info.clone(),
-                payer_info.clone(),
-                tokenprogram_account_info.clone(),
-            ],
-        )?;
+        // transferable recipe accounts may hold other receipts and are left open
+        if config_data.recipe_mode != RecipeMode::Transferable {
+            verbose_msg!("close {}", user_recipe_token_account_info.key);
+            invoke(
+                &spl_token_2022::instruction::close_account(
+                    tokenprogram_account_info.key,
+                    user_recipe_token_account_info.key,
+                    payer_info.key,
+                    payer_info.key,
+                    &[payer_info.key],
+                )?,
+                &[
+                    user_recipe_token_account_info.clone(),
+                    payer_info.clone(),
+                    payer_info.clone(),
+                    tokenprogram_account_info.clone(),
+                ],
+            )?;
+        }
         //TODO
 
         **payer_info.try_borrow_mut_lamports()? = payer_info
@@ -784,6 +1344,259 @@ impl Processor {
         **pda_user_account_info.try_borrow_mut_lamports()? = 0;
         *pda_user_account_info.try_borrow_mut_data()? = &mut [];
 
+        config_data.active_deposits = config_data
+            .active_deposits
+            .checked_sub(1)
+            .ok_or(LunabankError::AmountOverflow)?;
+        config_data.save(config_account_info)?;
+        if let Some((asset_info, asset)) = &mut pool.asset {
+            asset.active_deposits = asset
+                .active_deposits
+                .checked_sub(1)
+                .ok_or(LunabankError::AmountOverflow)?;
+            asset.save(asset_info)?;
+        }
+
+        LunabankEvent::Withdraw(WithdrawEvent {
+            bank: *config_account_info.key,
+            owner: *payer_info.key,
+            amount,
+            timestamp: cur_ts,
+        })
+        .emit();
+
+        Ok(())
+    }
+
+    fn process_flash_loan(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+        amount: u64,
+    ) -> ProgramResult {
+        let FlashLoanAccounts {
+            borrower: borrower_info,
+            config: config_account_info,
+            base_token_mint: base_token_mint_info,
+            base_token_account: base_token_account_info,
+            borrower_base_token_account: borrower_base_token_account_info,
+            pda: pda_account_info,
+            token_program: tokenprogram_account_info,
+            borrower_program: borrower_program_info,
+        } = FlashLoanAccounts::parse(program_id, accounts)?;
+        let borrower_accounts = &accounts[FlashLoanAccounts::SPECS.len()..];
+
+        // the bank is only checked and locked, through the view instead of decoding it
+        let pda_bump_seed = {
+            let config_data = config_account_info.try_borrow_data()?;
+            let config = LunabankView::load(&config_data)?;
+            if bool::from(config.is_locked) {
+                return Err(LunabankError::BankLocked.into());
+            }
+            if bool::from(config.is_shutdown) {
+                return Err(LunabankError::BankShutdown.into());
+            }
+            if config.base_token_mint != *base_token_mint_info.key {
+                return Err(LunabankError::BaseMintMismatch.into());
+            }
+            if config.base_token_account != *base_token_account_info.key {
+                return Err(LunabankError::VaultMismatch.into());
+            }
+            if config.token_program != *tokenprogram_account_info.key {
+                return Err(LunabankError::TokenProgramMismatch.into());
+            }
+            [config.pda_bump_seed]
+        };
+        let pda_seeds = &bank_authority_seeds(config_account_info.key, &pda_bump_seed);
+        assert_derivation_with_bump(program_id, pda_account_info, pda_seeds)?;
+
+        let base_token_mint_data = unpack_mint(base_token_mint_info)?;
+        let fee = Lunabank::flash_loan_fee(amount).ok_or(LunabankError::AmountOverflow)?;
+        let repaid_balance = unpack_token_account(base_token_account_info)?
+            .amount
+            .checked_add(fee)
+            .ok_or(LunabankError::AmountOverflow)?;
+
+        // set before anything leaves the vault, the borrower program may call back into
+        // lunabank
+        LunabankView::load_mut(&mut config_account_info.try_borrow_mut_data()?)?.is_locked =
+            true.into();
+
+        invoke_signed(
+            &spl_token_2022::instruction::transfer_checked(
+                tokenprogram_account_info.key,
+                base_token_account_info.key,
+                base_token_mint_info.key,
+                borrower_base_token_account_info.key,
+                pda_account_info.key,
+                &[pda_account_info.key],
+                amount,
+                base_token_mint_data.decimals,
+            )?,
+            &[
+                base_token_account_info.clone(),
+                base_token_mint_info.clone(),
+                borrower_base_token_account_info.clone(),
+                pda_account_info.clone(),
+            ],
+            &[pda_seeds],
+        )?;
+
+        verbose_msg!(
+            "Calling the borrower program {}...",
+            borrower_program_info.key
+        );
+        let borrower_ix = Instruction {
+            program_id: *borrower_program_info.key,
+            accounts: borrower_accounts
+                .iter()
+                .map(|account| AccountMeta {
+                    pubkey: *account.key,
+                    is_signer: account.is_signer,
+                    is_writable: account.is_writable,
+                })
+                .collect(),
+            data: [amount.to_le_bytes(), fee.to_le_bytes()].concat(),
+        };
+        let mut borrower_infos = borrower_accounts.to_vec();
+        borrower_infos.push(borrower_program_info.clone());
+        // not signed, the bank authority is only lent to the token program
+        invoke(&borrower_ix, &borrower_infos)?;
+
+        if unpack_token_account(base_token_account_info)?.amount < repaid_balance {
+            return Err(LunabankError::FlashLoanNotRepaid.into());
+        }
+
+        LunabankView::load_mut(&mut config_account_info.try_borrow_mut_data()?)?.is_locked =
+            false.into();
+
+        LunabankEvent::FlashLoan(FlashLoanEvent {
+            bank: *config_account_info.key,
+            borrower: *borrower_info.key,
+            borrower_program: *borrower_program_info.key,
+            amount,
+            fee,
+        })
+        .emit();
+
+        Ok(())
+    }
+
+    fn process_shutdown(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let ShutdownAccounts {
+            owner: initializer_info,
+            config: config_account_info,
+        } = ShutdownAccounts::parse(program_id, accounts)?;
+
+        let mut config_data = Lunabank::from_account_info(config_account_info)?;
+        if !config_data.is_initialized() {
+            return Err(LunabankError::UninitializedAccount.into());
+        }
+        if config_data.is_locked {
+            return Err(LunabankError::BankLocked.into());
+        }
+        if config_data.owner != *initializer_info.key {
+            return Err(LunabankError::BankOwnerMismatch.into());
+        }
+        if config_data.is_shutdown {
+            return Err(LunabankError::BankShutdown.into());
+        }
+
+        msg!(
+            "Shutting down bank, {} deposits outstanding",
+            config_data.active_deposits
+        );
+        config_data.is_shutdown = true;
+        config_data.save(config_account_info)?;
+
+        Ok(())
+    }
+
+    fn process_migrate(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let MigrateAccounts {
+            payer: payer_info,
+            account: account_info,
+            bank: config_account_info,
+            rent_sysvar: sysvar_account_info,
+            system_program: system_program_info,
+        } = MigrateAccounts::parse(program_id, accounts)?;
+        let rent = &Rent::from_account_info(sysvar_account_info)?;
+
+        let key = Key::deserialize(&mut &account_info.try_borrow_data()?[..])
+            .map_err(|_| LunabankError::DataTypeMismatch)?;
+        match key {
+            Key::LunabankV1 => {
+                assert_account_key(config_account_info, account_info.key)?;
+                let mut config_data =
+                    Lunabank::unpack_from_slice(&account_info.try_borrow_data()?)?;
+                config_data.key = Key::LunabankV2;
+                config_data.pda_bump_seed =
+                    find_bank_authority_address(program_id, config_account_info.key).1;
+                resize_account(
+                    account_info,
+                    payer_info,
+                    system_program_info,
+                    rent,
+                    Lunabank::LEN,
+                )?;
+                config_data.save(account_info)?;
+            }
+            // deposits predate assets, they all belong to the bank itself
+            Key::LunaDepositV1 | Key::LunaDepositV2 => {
+                let mut config_data = Lunabank::from_account_info(config_account_info)?;
+                let mut deposit_data =
+                    LunaDeposit::unpack_from_slice(&account_info.try_borrow_data()?)?;
+                if key == Key::LunaDepositV1 {
+                    deposit_data.bump_seed = assert_derived_key(
+                        account_info,
+                        find_deposit_address(
+                            program_id,
+                            config_account_info.key,
+                            &deposit_data.owner,
+                        ),
+                    )?;
+                } else {
+                    let bump_seed = [deposit_data.bump_seed];
+                    assert_derivation_with_bump(
+                        program_id,
+                        account_info,
+                        &deposit_seeds(config_account_info.key, &deposit_data.owner, &bump_seed),
+                    )?;
+                }
+                deposit_data.key = Key::LunaDepositV3;
+                deposit_data.bank = *config_account_info.key;
+                deposit_data.base_token_mint = config_data.base_token_mint;
+                resize_account(
+                    account_info,
+                    payer_info,
+                    system_program_info,
+                    rent,
+                    LunaDeposit::LEN,
+                )?;
+                deposit_data.save(account_info)?;
+                // V1 banks did not count their deposits
+                if key == Key::LunaDepositV1 {
+                    config_data.active_deposits = config_data
+                        .active_deposits
+                        .checked_add(1)
+                        .ok_or(LunabankError::AmountOverflow)?;
+                    config_data.save(config_account_info)?;
+                }
+            }
+            // already in the current layout
+            Key::LunabankV2 => {
+                Lunabank::from_account_info(account_info)?;
+            }
+            Key::LunaDepositV3 => {
+                LunaDeposit::from_account_info(account_info)?;
+            }
+            Key::LunaAssetV1 => {
+                LunaAsset::from_account_info(account_info)?;
+            }
+            Key::Uninitialized => return Err(LunabankError::UninitializedAccount.into()),
+            Key::LunaRegistryEntryV1 => return Err(LunabankError::DataTypeMismatch.into()),
+        }
+        verbose_msg!("Migrated {} from {:?}", account_info.key, key);
+
         Ok(())
     }
 }
@@ -1,26 +1,213 @@
-use crate::utils::try_from_slice_checked;
+use crate::{error::LunabankError, utils::try_from_slice_checked};
 //use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
 use solana_program::{
     account_info::AccountInfo,
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack, Sealed},
-    pubkey::Pubkey,
+    pubkey::{Pubkey, PubkeyError},
 };
 
 pub const LUNA_OWNER_PREFIX: &str = "lunaowner";
 pub const LUNA_DEPOSIT_PREFIX: &str = "deposit";
+pub const LUNA_BANK_PREFIX: &str = "lunabank";
+pub const LUNA_REGISTRY_PREFIX: &str = "registry";
+pub const LUNA_VAULT_PREFIX: &str = "vault";
+pub const LUNA_RECIPE_PREFIX: &str = "recipe";
+pub const LUNA_UNWRAP_PREFIX: &str = "unwrap";
+pub const LUNA_ASSET_PREFIX: &str = "asset";
+
+/// Flash loan fee in basis points of the amount borrowed, it stays in the vault and
+/// goes to the owner with the vault when the bank is closed
+pub const FLASH_LOAN_FEE_BPS: u64 = 9;
+
+/// Config account of the `bank_id`-th bank of `base_token_mint`
+pub fn find_bank_address(
+    program_id: &Pubkey,
+    base_token_mint: &Pubkey,
+    bank_id: u64,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            LUNA_BANK_PREFIX.as_bytes(),
+            base_token_mint.as_ref(),
+            &bank_id.to_le_bytes(),
+        ],
+        program_id,
+    )
+}
+
+/// Token account holding the base tokens of the bank, or of the asset when `config` is
+/// an asset account
+pub fn find_vault_address(program_id: &Pubkey, config: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[config.as_ref(), LUNA_VAULT_PREFIX.as_bytes()], program_id)
+}
+
+/// Mint of the recipe tokens handed out for deposits, in the bank or the asset `config`
+pub fn find_recipe_mint_address(program_id: &Pubkey, config: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[config.as_ref(), LUNA_RECIPE_PREFIX.as_bytes()],
+        program_id,
+    )
+}
+
+/// Registry entry of the `bank_id`-th bank of `base_token_mint`
+pub fn find_registry_address(
+    program_id: &Pubkey,
+    base_token_mint: &Pubkey,
+    bank_id: u64,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            LUNA_REGISTRY_PREFIX.as_bytes(),
+            base_token_mint.as_ref(),
+            &bank_id.to_le_bytes(),
+        ],
+        program_id,
+    )
+}
+
+/// Signer seeds of the bank authority, which owns the vault and is the authority
+/// of the recipe mint
+pub fn bank_authority_seeds<'a>(config: &'a Pubkey, bump_seed: &'a [u8; 1]) -> [&'a [u8]; 3] {
+    [config.as_ref(), LUNA_OWNER_PREFIX.as_bytes(), bump_seed]
+}
+
+pub fn find_bank_authority_address(program_id: &Pubkey, config: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[config.as_ref(), LUNA_OWNER_PREFIX.as_bytes()], program_id)
+}
+
+/// Bank authority for the bump seed stored in `Lunabank::pda_bump_seed`
+pub fn create_bank_authority_address(
+    program_id: &Pubkey,
+    config: &Pubkey,
+    bump_seed: u8,
+) -> Result<Pubkey, PubkeyError> {
+    Pubkey::create_program_address(&bank_authority_seeds(config, &[bump_seed]), program_id)
+}
+
+/// Signer seeds of the deposit account of `user` in the bank `config`
+pub fn deposit_seeds<'a>(
+    config: &'a Pubkey,
+    user: &'a Pubkey,
+    bump_seed: &'a [u8; 1],
+) -> [&'a [u8]; 4] {
+    [
+        config.as_ref(),
+        user.as_ref(),
+        LUNA_DEPOSIT_PREFIX.as_bytes(),
+        bump_seed,
+    ]
+}
+
+pub fn find_deposit_address(program_id: &Pubkey, config: &Pubkey, user: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            config.as_ref(),
+            user.as_ref(),
+            LUNA_DEPOSIT_PREFIX.as_bytes(),
+        ],
+        program_id,
+    )
+}
+
+/// Deposit account for the bump seed stored in `LunaDeposit::bump_seed`
+pub fn create_deposit_address(
+    program_id: &Pubkey,
+    config: &Pubkey,
+    user: &Pubkey,
+    bump_seed: u8,
+) -> Result<Pubkey, PubkeyError> {
+    Pubkey::create_program_address(&deposit_seeds(config, user, &[bump_seed]), program_id)
+}
+
+/// Signer seeds of the asset account of `base_token_mint` in the bank `config`
+pub fn asset_seeds<'a>(
+    config: &'a Pubkey,
+    base_token_mint: &'a Pubkey,
+    bump_seed: &'a [u8; 1],
+) -> [&'a [u8]; 4] {
+    [
+        config.as_ref(),
+        base_token_mint.as_ref(),
+        LUNA_ASSET_PREFIX.as_bytes(),
+        bump_seed,
+    ]
+}
+
+/// Asset account of `base_token_mint` in the bank `config`. Vaults, recipe mints,
+/// deposits and unwrap accounts of the asset are derived from it the way the ones of
+/// the bank are derived from `config`.
+pub fn find_asset_address(
+    program_id: &Pubkey,
+    config: &Pubkey,
+    base_token_mint: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            config.as_ref(),
+            base_token_mint.as_ref(),
+            LUNA_ASSET_PREFIX.as_bytes(),
+        ],
+        program_id,
+    )
+}
+
+/// Signer seeds of the wrapped SOL account a withdrawal from a SOL bank goes through,
+/// it is created and closed within the instruction
+pub fn unwrap_seeds<'a>(
+    config: &'a Pubkey,
+    user: &'a Pubkey,
+    bump_seed: &'a [u8; 1],
+) -> [&'a [u8]; 4] {
+    [
+        config.as_ref(),
+        user.as_ref(),
+        LUNA_UNWRAP_PREFIX.as_bytes(),
+        bump_seed,
+    ]
+}
+
+pub fn find_unwrap_address(program_id: &Pubkey, config: &Pubkey, user: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            config.as_ref(),
+            user.as_ref(),
+            LUNA_UNWRAP_PREFIX.as_bytes(),
+        ],
+        program_id,
+    )
+}
 
 #[repr(C)]
-#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone, Copy)]
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, PartialEq, Debug, Clone, Copy)]
 pub enum Key {
     Uninitialized,
     LunabankV1,
     LunaDepositV1,
+    LunaRegistryEntryV1,
+    LunabankV2,
+    LunaDepositV2,
+    LunaAssetV1,
+    LunaDepositV3,
+}
+
+/// How a bank keeps track of the recipe tokens of open deposits
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, PartialEq, Debug, Clone, Copy)]
+pub enum RecipeMode {
+    /// The bank authority freezes the recipe account on deposit and thaws it on withdrawal
+    Frozen,
+    /// The recipe mint has the Token-2022 non-transferable extension, nothing is frozen.
+    /// Recipe accounts need the immutable owner extension, associated token accounts have it.
+    NonTransferable,
+    /// Nothing holds the recipe tokens back, withdrawing burns the deposited amount from any
+    /// recipe account the depositor controls, as owner or delegate
+    Transferable,
 }
 
 #[repr(C)]
-#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, PartialEq, Debug, Clone)]
 pub struct Lunabank {
     pub key: Key,
     pub owner: Pubkey,
@@ -28,6 +215,23 @@ pub struct Lunabank {
     pub recipe_token_mint: Pubkey,
     pub base_token_account: Pubkey,
     pub coef: u64,
+    pub is_shutdown: bool,
+    /// V1 banks start from 0, `Migrate` counts their deposits in as they are migrated
+    pub active_deposits: u64,
+    /// 0 for V1 banks, whose config accounts are no PDAs
+    pub bank_id: u64,
+    /// Found by `Migrate` for V1 banks
+    pub pda_bump_seed: u8,
+    /// `RecipeMode::Frozen` for V1 banks
+    pub recipe_mode: RecipeMode,
+    /// Owner of the base mint, the vault and the recipe mint, spl-token for V1 banks
+    pub token_program: Pubkey,
+    /// Set while a flash loan is out, the bank takes no other instruction until it is repaid
+    pub is_locked: bool,
+    /// `LunaAsset` accounts of the bank, `Close` waits for `RemoveAsset` to remove them all
+    pub asset_count: u64,
+    /// Zeroed, new fields are carved out of it without resizing the account
+    pub reserved: [u8; 22],
 }
 
 impl IsInitialized for Lunabank {
@@ -44,33 +248,141 @@ impl Pack for Lunabank {
         + 32 // base_token_mint
         + 32 // recipe_token_mint
         + 32 // base_token_account
-        + 8; // coef
+        + 8 // coef
+        + 1 // is_shutdown
+        + 8 // active_deposits
+        + 8 // bank_id
+        + 1 // pda_bump_seed
+        + 1 // recipe_mode
+        + 32 // token_program
+        + 1 // is_locked
+        + 8 // asset_count
+        + 22; // reserved
     fn pack_into_slice(&self, dst: &mut [u8]) {
         let mut slice = dst;
         self.serialize(&mut slice).unwrap();
     }
 
     fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
-        let luna_bank: Lunabank = try_from_slice_checked(src, Key::LunabankV1, Self::LEN)?;
+        if src.first() == Some(&(Key::LunabankV1 as u8)) {
+            let luna_bank: LunabankV1 = try_from_slice_checked(src, Key::LunabankV1, Self::LEN_V1)?;
+            return Ok(luna_bank.into());
+        }
+        let luna_bank: Lunabank = try_from_slice_checked(src, Key::LunabankV2, Self::LEN)?;
         Ok(luna_bank)
     }
 }
 
 impl Lunabank {
+    /// Size of `Key::LunabankV1` accounts, which end after `coef`
+    pub const LEN_V1: usize = 1 // key
+        + 32 // owner
+        + 32 // base_token_mint
+        + 32 // recipe_token_mint
+        + 32 // base_token_account
+        + 8; // coef
+
+    // Field offsets in both layouts, Borsh writes the fields back to back
+    pub const KEY_OFFSET: usize = 0;
+    pub const OWNER_OFFSET: usize = 1;
+    pub const BASE_TOKEN_MINT_OFFSET: usize = Self::OWNER_OFFSET + 32;
+    pub const RECIPE_TOKEN_MINT_OFFSET: usize = Self::BASE_TOKEN_MINT_OFFSET + 32;
+    pub const BASE_TOKEN_ACCOUNT_OFFSET: usize = Self::RECIPE_TOKEN_MINT_OFFSET + 32;
+    pub const COEF_OFFSET: usize = Self::BASE_TOKEN_ACCOUNT_OFFSET + 32;
+    /// Only in V2 accounts, like every field after it
+    pub const IS_SHUTDOWN_OFFSET: usize = Self::COEF_OFFSET + 8;
+    pub const ACTIVE_DEPOSITS_OFFSET: usize = Self::IS_SHUTDOWN_OFFSET + 1;
+    pub const BANK_ID_OFFSET: usize = Self::ACTIVE_DEPOSITS_OFFSET + 8;
+    pub const PDA_BUMP_SEED_OFFSET: usize = Self::BANK_ID_OFFSET + 8;
+    pub const RECIPE_MODE_OFFSET: usize = Self::PDA_BUMP_SEED_OFFSET + 1;
+    pub const TOKEN_PROGRAM_OFFSET: usize = Self::RECIPE_MODE_OFFSET + 1;
+    pub const IS_LOCKED_OFFSET: usize = Self::TOKEN_PROGRAM_OFFSET + 32;
+    pub const ASSET_COUNT_OFFSET: usize = Self::IS_LOCKED_OFFSET + 1;
+
+    /// Bank in the current layout, V1 banks have to be migrated first
     pub fn from_account_info(a: &AccountInfo) -> Result<Lunabank, ProgramError> {
         let lunabank: Lunabank = Self::unpack_from_slice(&a.data.borrow_mut())?;
+        if lunabank.key == Key::LunabankV1 {
+            return Err(LunabankError::AccountNotMigrated.into());
+        }
         Ok(lunabank)
     }
+
+    /// SOL banks hold wrapped SOL, deposits are paid and withdrawals returned in lamports
+    pub fn is_native(&self) -> bool {
+        self.base_token_mint == spl_token::native_mint::id()
+    }
+
+    /// Fee on top of `amount` a flash loan has to pay back, rounded up so that no loan
+    /// is free. `None` on overflow.
+    pub fn flash_loan_fee(amount: u64) -> Option<u64> {
+        let fee = (amount as u128 * FLASH_LOAN_FEE_BPS as u128).div_ceil(10_000);
+        u64::try_from(fee).ok()
+    }
+
+    /// Account size of the layout `key` stands for
+    pub fn data_len(&self) -> usize {
+        match self.key {
+            Key::LunabankV1 => Self::LEN_V1,
+            _ => Self::LEN,
+        }
+    }
+
+    /// Writes the bank in the current layout, the account has to be migrated already
+    pub fn save(&self, a: &AccountInfo) -> Result<(), ProgramError> {
+        save_sized(self, a, Self::LEN)
+    }
+}
+
+/// `Key::LunabankV1` layout, the one of the banks created before `Migrate`
+#[derive(BorshDeserialize)]
+struct LunabankV1 {
+    key: Key,
+    owner: Pubkey,
+    base_token_mint: Pubkey,
+    recipe_token_mint: Pubkey,
+    base_token_account: Pubkey,
+    coef: u64,
+}
+
+impl From<LunabankV1> for Lunabank {
+    fn from(v1: LunabankV1) -> Self {
+        Lunabank {
+            key: v1.key,
+            owner: v1.owner,
+            base_token_mint: v1.base_token_mint,
+            recipe_token_mint: v1.recipe_token_mint,
+            base_token_account: v1.base_token_account,
+            coef: v1.coef,
+            is_shutdown: false,
+            active_deposits: 0,
+            bank_id: 0,
+            pda_bump_seed: 0,
+            recipe_mode: RecipeMode::Frozen,
+            token_program: spl_token::id(),
+            is_locked: false,
+            asset_count: 0,
+            reserved: [0; 22],
+        }
+    }
 }
 
 #[repr(C)]
-#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, PartialEq, Debug, Clone)]
 pub struct LunaDeposit {
     pub key: Key,
     pub owner: Pubkey,
     pub amount: u64,
     pub start_timestamp: u64,
     pub end_timestamp: u64,
+    /// Found by `Migrate` for V1 deposits
+    pub bump_seed: u8,
+    /// Config account of the bank, `Pubkey::default()` for V1 and V2 deposits until migrated
+    pub bank: Pubkey,
+    /// Base token mint of the bank, `Pubkey::default()` for V1 and V2 deposits until migrated
+    pub base_token_mint: Pubkey,
+    /// Zeroed, new fields are carved out of it without resizing the account
+    pub reserved: [u8; 32],
 }
 
 impl Sealed for LunaDeposit {}
@@ -80,14 +392,28 @@ impl Pack for LunaDeposit {
         + 32 // owner
         + 8 // amount
         + 8 // start_timestamp
-        + 8; // end_timestamp
+        + 8 // end_timestamp
+        + 1 // bump_seed
+        + 32 // bank
+        + 32 // base_token_mint
+        + 32; // reserved
     fn pack_into_slice(&self, dst: &mut [u8]) {
         let mut slice = dst;
         self.serialize(&mut slice).unwrap();
     }
 
     fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
-        let luna_deposit: LunaDeposit = try_from_slice_checked(src, Key::LunaDepositV1, Self::LEN)?;
+        if src.first() == Some(&(Key::LunaDepositV1 as u8)) {
+            let luna_deposit: LunaDepositV1 =
+                try_from_slice_checked(src, Key::LunaDepositV1, Self::LEN_V1)?;
+            return Ok(luna_deposit.into());
+        }
+        if src.first() == Some(&(Key::LunaDepositV2 as u8)) {
+            let luna_deposit: LunaDepositV2 =
+                try_from_slice_checked(src, Key::LunaDepositV2, Self::LEN_V2)?;
+            return Ok(luna_deposit.into());
+        }
+        let luna_deposit: LunaDeposit = try_from_slice_checked(src, Key::LunaDepositV3, Self::LEN)?;
         Ok(luna_deposit)
     }
 }
@@ -99,8 +425,651 @@ impl IsInitialized for LunaDeposit {
 }
 
 impl LunaDeposit {
+    /// Size of `Key::LunaDepositV1` accounts, which end after `end_timestamp`
+    pub const LEN_V1: usize = 1 // key
+        + 32 // owner
+        + 8 // amount
+        + 8 // start_timestamp
+        + 8; // end_timestamp
+
+    /// Size of `Key::LunaDepositV2` accounts, which end after `bump_seed` and a reserved
+    /// space too short for `bank` and `base_token_mint`
+    pub const LEN_V2: usize = Self::LEN_V1
+        + 1 // bump_seed
+        + 32; // reserved
+
+    // Field offsets in every layout, Borsh writes the fields back to back
+    pub const KEY_OFFSET: usize = 0;
+    pub const OWNER_OFFSET: usize = 1;
+    pub const AMOUNT_OFFSET: usize = Self::OWNER_OFFSET + 32;
+    pub const START_TIMESTAMP_OFFSET: usize = Self::AMOUNT_OFFSET + 8;
+    pub const END_TIMESTAMP_OFFSET: usize = Self::START_TIMESTAMP_OFFSET + 8;
+    /// Not in V1 accounts
+    pub const BUMP_SEED_OFFSET: usize = Self::END_TIMESTAMP_OFFSET + 8;
+    /// Only in V3 accounts, like every field after it
+    pub const BANK_OFFSET: usize = Self::BUMP_SEED_OFFSET + 1;
+    pub const BASE_TOKEN_MINT_OFFSET: usize = Self::BANK_OFFSET + 32;
+
+    /// Deposit in the current layout, V1 and V2 deposits have to be migrated first
     pub fn from_account_info(a: &AccountInfo) -> Result<LunaDeposit, ProgramError> {
         let luna_deposit: LunaDeposit = Self::unpack_from_slice(&a.data.borrow_mut())?;
+        if luna_deposit.key != Key::LunaDepositV3 {
+            return Err(LunabankError::AccountNotMigrated.into());
+        }
         Ok(luna_deposit)
     }
+
+    /// Account size of the layout `key` stands for
+    pub fn data_len(&self) -> usize {
+        match self.key {
+            Key::LunaDepositV1 => Self::LEN_V1,
+            Key::LunaDepositV2 => Self::LEN_V2,
+            _ => Self::LEN,
+        }
+    }
+
+    /// Writes the deposit in the current layout, the account has to be migrated already
+    pub fn save(&self, a: &AccountInfo) -> Result<(), ProgramError> {
+        save_sized(self, a, Self::LEN)
+    }
+}
+
+/// `Key::LunaDepositV1` layout, the one of the deposits made before `Migrate`
+#[derive(BorshDeserialize)]
+struct LunaDepositV1 {
+    key: Key,
+    owner: Pubkey,
+    amount: u64,
+    start_timestamp: u64,
+    end_timestamp: u64,
+}
+
+impl From<LunaDepositV1> for LunaDeposit {
+    fn from(v1: LunaDepositV1) -> Self {
+        LunaDeposit {
+            key: v1.key,
+            owner: v1.owner,
+            amount: v1.amount,
+            start_timestamp: v1.start_timestamp,
+            end_timestamp: v1.end_timestamp,
+            bump_seed: 0,
+            bank: Pubkey::default(),
+            base_token_mint: Pubkey::default(),
+            reserved: [0; 32],
+        }
+    }
+}
+
+/// `Key::LunaDepositV2` layout, the one of the deposits made before they recorded their bank
+#[derive(BorshDeserialize)]
+struct LunaDepositV2 {
+    key: Key,
+    owner: Pubkey,
+    amount: u64,
+    start_timestamp: u64,
+    end_timestamp: u64,
+    bump_seed: u8,
+    /// Always zeroed, too short for the fields that came next
+    _reserved: [u8; 32],
+}
+
+impl From<LunaDepositV2> for LunaDeposit {
+    fn from(v2: LunaDepositV2) -> Self {
+        LunaDeposit {
+            key: v2.key,
+            owner: v2.owner,
+            amount: v2.amount,
+            start_timestamp: v2.start_timestamp,
+            end_timestamp: v2.end_timestamp,
+            bump_seed: v2.bump_seed,
+            bank: Pubkey::default(),
+            base_token_mint: Pubkey::default(),
+            reserved: [0; 32],
+        }
+    }
+}
+
+/// Additional base mint accepted by a bank, with a vault and a recipe mint of its own.
+/// The owner, the shutdown flag, the recipe mode and the token program are the ones of
+/// the bank, so is the bank authority owning the vault and minting the recipe tokens.
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, PartialEq, Debug, Clone)]
+pub struct LunaAsset {
+    pub key: Key,
+    /// Config account of the bank
+    pub bank: Pubkey,
+    pub base_token_mint: Pubkey,
+    pub recipe_token_mint: Pubkey,
+    pub base_token_account: Pubkey,
+    /// Open deposits of the asset, also counted in `Lunabank::active_deposits`
+    pub active_deposits: u64,
+    pub bump_seed: u8,
+    /// Zeroed, new fields are carved out of it without resizing the account
+    pub reserved: [u8; 32],
+}
+
+impl Sealed for LunaAsset {}
+
+impl Pack for LunaAsset {
+    const LEN: usize = 1 // key
+        + 32 // bank
+        + 32 // base_token_mint
+        + 32 // recipe_token_mint
+        + 32 // base_token_account
+        + 8 // active_deposits
+        + 1 // bump_seed
+        + 32; // reserved
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let mut slice = dst;
+        self.serialize(&mut slice).unwrap();
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        try_from_slice_checked(src, Key::LunaAssetV1, Self::LEN)
+    }
+}
+
+impl IsInitialized for LunaAsset {
+    fn is_initialized(&self) -> bool {
+        self.key != Key::Uninitialized
+    }
+}
+
+impl LunaAsset {
+    pub const KEY_OFFSET: usize = 0;
+    pub const BANK_OFFSET: usize = 1;
+    pub const BASE_TOKEN_MINT_OFFSET: usize = Self::BANK_OFFSET + 32;
+
+    pub fn from_account_info(a: &AccountInfo) -> Result<LunaAsset, ProgramError> {
+        Self::unpack_from_slice(&a.data.borrow())
+    }
+
+    pub fn save(&self, a: &AccountInfo) -> Result<(), ProgramError> {
+        save_sized(self, a, Self::LEN)
+    }
+}
+
+/// Serialize `value` into an account of exactly `data_size` bytes
+fn save_sized<T: BorshSerialize>(
+    value: &T,
+    a: &AccountInfo,
+    data_size: usize,
+) -> Result<(), ProgramError> {
+    let mut data = a.try_borrow_mut_data()?;
+    if data.len() != data_size {
+        return Err(LunabankError::DataSizeMismatch.into());
+    }
+    let bytes = value.try_to_vec()?;
+    data.copy_from_slice(&bytes[..data_size]);
+    Ok(())
+}
+
+/// Registry entry of one bank, at the PDA ("registry" + base token mint + bank_id). `Init`
+/// creates it and `Close` closes it, clients list the banks of a mint with
+/// `filters::registry_entries_by_base_mint`.
+#[repr(C)]
+#[derive(BorshSerialize, BorshDeserialize, BorshSchema, PartialEq, Debug, Clone)]
+pub struct LunaRegistryEntry {
+    pub key: Key,
+    /// Config account of the bank
+    pub bank: Pubkey,
+    pub base_token_mint: Pubkey,
+    pub bank_id: u64,
+}
+
+impl Sealed for LunaRegistryEntry {}
+
+impl Pack for LunaRegistryEntry {
+    const LEN: usize = 1 // key
+        + 32 // bank
+        + 32 // base_token_mint
+        + 8; // bank_id
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let mut slice = dst;
+        self.serialize(&mut slice).unwrap();
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        try_from_slice_checked(src, Key::LunaRegistryEntryV1, Self::LEN)
+    }
+}
+
+impl IsInitialized for LunaRegistryEntry {
+    fn is_initialized(&self) -> bool {
+        self.key != Key::Uninitialized
+    }
+}
+
+impl LunaRegistryEntry {
+    pub const KEY_OFFSET: usize = 0;
+    pub const BANK_OFFSET: usize = 1;
+    pub const BASE_TOKEN_MINT_OFFSET: usize = Self::BANK_OFFSET + 32;
+
+    pub fn from_account_info(a: &AccountInfo) -> Result<LunaRegistryEntry, ProgramError> {
+        Self::unpack_from_slice(&a.data.borrow())
+    }
+
+    pub fn save(&self, a: &AccountInfo) -> Result<(), ProgramError> {
+        save_sized(self, a, Self::LEN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_pinned_addresses() {
+        let program_id = Pubkey::new_from_array([1; 32]);
+        let config = Pubkey::new_from_array([2; 32]);
+        let user = Pubkey::new_from_array([3; 32]);
+
+        let authority = Pubkey::from_str("B6NAjvFtUXUerjUDfwTRcsPN9dkUiA5MYcYFicLvGh6u").unwrap();
+        assert_eq!(
+            find_bank_authority_address(&program_id, &config),
+            (authority, 255)
+        );
+        assert_eq!(
+            create_bank_authority_address(&program_id, &config, 255),
+            Ok(authority)
+        );
+
+        let deposit = Pubkey::from_str("2moyDYCMfrkKdNhdQKdExc3gQEXL8cSJRMcx1zsWRxUw").unwrap();
+        assert_eq!(
+            find_deposit_address(&program_id, &config, &user),
+            (deposit, 253)
+        );
+        assert_eq!(
+            create_deposit_address(&program_id, &config, &user, 253),
+            Ok(deposit)
+        );
+        assert_ne!(
+            create_deposit_address(&program_id, &config, &user, 254),
+            Ok(deposit)
+        );
+    }
+
+    fn bank() -> Lunabank {
+        Lunabank {
+            key: Key::LunabankV2,
+            owner: Pubkey::new_unique(),
+            base_token_mint: Pubkey::new_unique(),
+            recipe_token_mint: Pubkey::new_unique(),
+            base_token_account: Pubkey::new_unique(),
+            coef: 1,
+            is_shutdown: false,
+            active_deposits: 2,
+            bank_id: 3,
+            pda_bump_seed: 254,
+            recipe_mode: RecipeMode::Frozen,
+            token_program: spl_token::id(),
+            is_locked: false,
+            asset_count: 0,
+            reserved: [0; 22],
+        }
+    }
+
+    fn deposit() -> LunaDeposit {
+        LunaDeposit {
+            key: Key::LunaDepositV3,
+            owner: Pubkey::new_unique(),
+            amount: 100,
+            start_timestamp: 10,
+            end_timestamp: 20,
+            bump_seed: 253,
+            bank: Pubkey::new_unique(),
+            base_token_mint: Pubkey::new_unique(),
+            reserved: [0; 32],
+        }
+    }
+
+    fn packed<T: Pack>(value: T) -> Vec<u8> {
+        let mut data = vec![0; T::LEN];
+        T::pack(value, &mut data).unwrap();
+        data
+    }
+
+    fn asset() -> LunaAsset {
+        LunaAsset {
+            key: Key::LunaAssetV1,
+            bank: Pubkey::new_unique(),
+            base_token_mint: Pubkey::new_unique(),
+            recipe_token_mint: Pubkey::new_unique(),
+            base_token_account: Pubkey::new_unique(),
+            active_deposits: 4,
+            bump_seed: 252,
+            reserved: [0; 32],
+        }
+    }
+
+    #[test]
+    fn test_pack_roundtrip() {
+        let bank = bank();
+        assert_eq!(Lunabank::unpack(&packed(bank.clone())).unwrap(), bank);
+
+        let deposit = deposit();
+        assert_eq!(
+            LunaDeposit::unpack(&packed(deposit.clone())).unwrap(),
+            deposit
+        );
+
+        let asset = asset();
+        let data = packed(asset.clone());
+        assert_eq!(LunaAsset::unpack(&data).unwrap(), asset);
+        assert_eq!(
+            data[LunaAsset::BANK_OFFSET..LunaAsset::BANK_OFFSET + 32],
+            *asset.bank.as_ref()
+        );
+        assert_eq!(
+            data[LunaAsset::BASE_TOKEN_MINT_OFFSET..LunaAsset::BASE_TOKEN_MINT_OFFSET + 32],
+            *asset.base_token_mint.as_ref()
+        );
+    }
+
+    #[test]
+    fn test_empty_data() {
+        assert_eq!(
+            Lunabank::unpack_from_slice(&[]),
+            Err(LunabankError::DataSizeMismatch.into())
+        );
+        assert_eq!(
+            LunaDeposit::unpack_from_slice(&[]),
+            Err(LunabankError::DataSizeMismatch.into())
+        );
+    }
+
+    #[test]
+    fn test_truncated_data() {
+        let data = packed(bank());
+        assert_eq!(
+            Lunabank::unpack_from_slice(&data[..Lunabank::LEN - 1]),
+            Err(LunabankError::DataSizeMismatch.into())
+        );
+        assert_eq!(
+            Lunabank::unpack_from_slice(&data[..1]),
+            Err(LunabankError::DataSizeMismatch.into())
+        );
+
+        let data = packed(deposit());
+        assert_eq!(
+            LunaDeposit::unpack_from_slice(&data[..LunaDeposit::LEN - 1]),
+            Err(LunabankError::DataSizeMismatch.into())
+        );
+        assert_eq!(
+            LunaDeposit::unpack_from_slice(&data[..1]),
+            Err(LunabankError::DataSizeMismatch.into())
+        );
+    }
+
+    #[test]
+    fn test_oversized_data() {
+        let mut data = packed(bank());
+        data.push(0);
+        assert_eq!(
+            Lunabank::unpack_from_slice(&data),
+            Err(LunabankError::DataSizeMismatch.into())
+        );
+
+        let mut data = packed(deposit());
+        data.push(0);
+        assert_eq!(
+            LunaDeposit::unpack_from_slice(&data),
+            Err(LunabankError::DataSizeMismatch.into())
+        );
+    }
+
+    #[test]
+    fn test_uninitialized_data() {
+        assert_eq!(
+            Lunabank::unpack_from_slice(&[0; Lunabank::LEN]),
+            Err(LunabankError::UninitializedAccount.into())
+        );
+        assert_eq!(
+            LunaDeposit::unpack_from_slice(&[0; LunaDeposit::LEN]),
+            Err(LunabankError::UninitializedAccount.into())
+        );
+    }
+
+    #[test]
+    fn test_cross_type_data() {
+        // the other type's account as is
+        assert_eq!(
+            Lunabank::unpack_from_slice(&packed(deposit())),
+            Err(LunabankError::DataSizeMismatch.into())
+        );
+        assert_eq!(
+            LunaDeposit::unpack_from_slice(&packed(bank())),
+            Err(LunabankError::DataSizeMismatch.into())
+        );
+
+        // the right size with the other type's key
+        let mut data = packed(bank());
+        data[0] = Key::LunaDepositV3 as u8;
+        assert_eq!(
+            Lunabank::unpack_from_slice(&data),
+            Err(LunabankError::DataTypeMismatch.into())
+        );
+
+        let mut data = packed(deposit());
+        data[0] = Key::LunabankV2 as u8;
+        assert_eq!(
+            LunaDeposit::unpack_from_slice(&data),
+            Err(LunabankError::DataTypeMismatch.into())
+        );
+
+        assert_eq!(
+            LunaAsset::unpack_from_slice(&packed(deposit())),
+            Err(LunabankError::DataSizeMismatch.into())
+        );
+        let mut data = packed(asset());
+        data[0] = Key::LunabankV2 as u8;
+        assert_eq!(
+            LunaAsset::unpack_from_slice(&data),
+            Err(LunabankError::DataTypeMismatch.into())
+        );
+    }
+
+    /// Bytes of a bank written by the V1 program, field by field
+    fn v1_bank_data(bank: &Lunabank) -> Vec<u8> {
+        let mut data = vec![Key::LunabankV1 as u8];
+        data.extend_from_slice(bank.owner.as_ref());
+        data.extend_from_slice(bank.base_token_mint.as_ref());
+        data.extend_from_slice(bank.recipe_token_mint.as_ref());
+        data.extend_from_slice(bank.base_token_account.as_ref());
+        data.extend_from_slice(&bank.coef.to_le_bytes());
+        data
+    }
+
+    /// Bytes of a deposit written by the V1 program, field by field
+    fn v1_deposit_data(deposit: &LunaDeposit) -> Vec<u8> {
+        let mut data = vec![Key::LunaDepositV1 as u8];
+        data.extend_from_slice(deposit.owner.as_ref());
+        data.extend_from_slice(&deposit.amount.to_le_bytes());
+        data.extend_from_slice(&deposit.start_timestamp.to_le_bytes());
+        data.extend_from_slice(&deposit.end_timestamp.to_le_bytes());
+        data
+    }
+
+    /// Bytes of a deposit written before `bank` and `base_token_mint`, field by field
+    fn v2_deposit_data(deposit: &LunaDeposit) -> Vec<u8> {
+        let mut data = v1_deposit_data(deposit);
+        data[0] = Key::LunaDepositV2 as u8;
+        data.push(deposit.bump_seed);
+        data.extend_from_slice(&[0; 32]);
+        data
+    }
+
+    #[test]
+    fn test_v1_layout() {
+        let bank = bank();
+        let data = v1_bank_data(&bank);
+        assert_eq!(data.len(), 137);
+        assert_eq!(Lunabank::LEN_V1, 137);
+        // V1 banks predate shutdowns, bank ids, stored bumps, recipe modes, Token-2022,
+        // flash loans and assets
+        assert_eq!(
+            Lunabank::unpack_from_slice(&data),
+            Ok(Lunabank {
+                key: Key::LunabankV1,
+                is_shutdown: false,
+                active_deposits: 0,
+                bank_id: 0,
+                pda_bump_seed: 0,
+                recipe_mode: RecipeMode::Frozen,
+                token_program: spl_token::id(),
+                is_locked: false,
+                asset_count: 0,
+                ..bank.clone()
+            })
+        );
+        // V1 accounts are never longer than their layout, nor cut short
+        let mut longer = data.clone();
+        longer.push(0);
+        assert_eq!(
+            Lunabank::unpack_from_slice(&longer),
+            Err(LunabankError::DataSizeMismatch.into())
+        );
+        assert_eq!(
+            Lunabank::unpack_from_slice(&data[..Lunabank::LEN_V1 - 1]),
+            Err(LunabankError::DataSizeMismatch.into())
+        );
+        // V2 banks are not read at the V1 size
+        let mut v2 = data.clone();
+        v2[0] = Key::LunabankV2 as u8;
+        assert_eq!(
+            Lunabank::unpack_from_slice(&v2),
+            Err(LunabankError::DataSizeMismatch.into())
+        );
+
+        let deposit = deposit();
+        let data = v1_deposit_data(&deposit);
+        assert_eq!(data.len(), 57);
+        assert_eq!(LunaDeposit::LEN_V1, 57);
+        // V1 deposits do not know their bump seed nor their bank
+        assert_eq!(
+            LunaDeposit::unpack_from_slice(&data),
+            Ok(LunaDeposit {
+                key: Key::LunaDepositV1,
+                bump_seed: 0,
+                bank: Pubkey::default(),
+                base_token_mint: Pubkey::default(),
+                ..deposit
+            })
+        );
+        let mut longer = data;
+        longer.push(0);
+        assert_eq!(
+            LunaDeposit::unpack_from_slice(&longer),
+            Err(LunabankError::DataSizeMismatch.into())
+        );
+    }
+
+    #[test]
+    fn test_v2_deposit_layout() {
+        let deposit = deposit();
+        let data = v2_deposit_data(&deposit);
+        assert_eq!(data.len(), 90);
+        assert_eq!(LunaDeposit::LEN_V2, 90);
+        // V2 deposits know their bump seed but not their bank
+        assert_eq!(
+            LunaDeposit::unpack_from_slice(&data),
+            Ok(LunaDeposit {
+                key: Key::LunaDepositV2,
+                bank: Pubkey::default(),
+                base_token_mint: Pubkey::default(),
+                ..deposit.clone()
+            })
+        );
+        let mut longer = data.clone();
+        longer.push(0);
+        assert_eq!(
+            LunaDeposit::unpack_from_slice(&longer),
+            Err(LunabankError::DataSizeMismatch.into())
+        );
+        // V3 deposits are not read at the V2 size, nor V2 ones at the V3 size
+        let mut v3 = data;
+        v3[0] = Key::LunaDepositV3 as u8;
+        assert_eq!(
+            LunaDeposit::unpack_from_slice(&v3),
+            Err(LunabankError::DataSizeMismatch.into())
+        );
+        let mut data = packed(deposit);
+        data[0] = Key::LunaDepositV2 as u8;
+        assert_eq!(
+            LunaDeposit::unpack_from_slice(&data),
+            Err(LunabankError::DataSizeMismatch.into())
+        );
+    }
+
+    #[test]
+    fn test_v1_accounts_are_not_used_unmigrated() {
+        let key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut lamports = 0;
+        let bank = bank();
+        let mut data = v1_bank_data(&bank);
+        let account = AccountInfo::new(
+            &key,
+            false,
+            true,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        assert_eq!(
+            Lunabank::from_account_info(&account),
+            Err(LunabankError::AccountNotMigrated.into())
+        );
+        // migrated in memory, but the account was not resized yet
+        assert_eq!(
+            bank.save(&account),
+            Err(LunabankError::DataSizeMismatch.into())
+        );
+
+        for mut data in [v1_deposit_data(&deposit()), v2_deposit_data(&deposit())] {
+            let mut lamports = 0;
+            let account = AccountInfo::new(
+                &key,
+                false,
+                true,
+                &mut lamports,
+                &mut data,
+                &owner,
+                false,
+                0,
+            );
+            assert_eq!(
+                LunaDeposit::from_account_info(&account),
+                Err(LunabankError::AccountNotMigrated.into())
+            );
+        }
+    }
+
+    #[test]
+    fn test_flash_loan_fee() {
+        assert_eq!(Lunabank::flash_loan_fee(0), Some(0));
+        assert_eq!(Lunabank::flash_loan_fee(1), Some(1));
+        assert_eq!(Lunabank::flash_loan_fee(10_000), Some(FLASH_LOAN_FEE_BPS));
+        assert_eq!(
+            Lunabank::flash_loan_fee(10_001),
+            Some(FLASH_LOAN_FEE_BPS + 1)
+        );
+        assert_eq!(
+            Lunabank::flash_loan_fee(u64::MAX),
+            Some(16_602_069_666_338_597)
+        );
+    }
+
+    #[test]
+    fn test_unknown_key() {
+        let mut data = packed(bank());
+        data[0] = u8::MAX;
+        assert_eq!(
+            Lunabank::unpack_from_slice(&data),
+            Err(LunabankError::DataTypeMismatch.into())
+        );
+    }
 }
@@ -1,22 +1,56 @@
 use crate::{error::LunabankError, state::Key};
 use borsh::BorshDeserialize;
 use solana_program::{
-    account_info::AccountInfo, borsh::try_from_slice_unchecked, entrypoint::ProgramResult,
-    program_error::ProgramError, pubkey::Pubkey,
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::rent::Rent,
+};
+use spl_token_2022::{
+    extension::{BaseStateWithExtensions, ExtensionType, StateWithExtensions},
+    state::{Account as TokenAccount, Mint},
 };
 
-pub fn assert_derivation(
-    program_id: &Pubkey,
+/// Mint extensions a bank can't hold: fees and hooks change what reaches the vault, a
+/// permanent delegate or a frozen default state take the vault out of the bank's control
+const UNSUPPORTED_MINT_EXTENSIONS: &[ExtensionType] = &[
+    ExtensionType::TransferFeeConfig,
+    ExtensionType::TransferHook,
+    ExtensionType::PermanentDelegate,
+    ExtensionType::DefaultAccountState,
+    ExtensionType::NonTransferable,
+];
+
+/// Checks `account` against an address computed by one of the `state::find_*_address`
+/// helpers and returns its bump seed
+pub fn assert_derived_key(
     account: &AccountInfo,
-    path: &[&[u8]],
+    (key, bump): (Pubkey, u8),
 ) -> Result<u8, ProgramError> {
-    let (key, bump) = Pubkey::find_program_address(&path, program_id);
     if key != *account.key {
         return Err(LunabankError::DerivedKeyInvalid.into());
     }
     Ok(bump)
 }
 
+/// Checks `account` against the address derived from `signer_seeds` with a known bump, the
+/// last element, which skips the bump search of `find_program_address`
+pub fn assert_derivation_with_bump(
+    program_id: &Pubkey,
+    account: &AccountInfo,
+    signer_seeds: &[&[u8]],
+) -> ProgramResult {
+    let key = Pubkey::create_program_address(signer_seeds, program_id)
+        .map_err(|_| LunabankError::DerivedKeyInvalid)?;
+    if key != *account.key {
+        return Err(LunabankError::DerivedKeyInvalid.into());
+    }
+    Ok(())
+}
+
 pub fn assert_signer(account_info: &AccountInfo) -> ProgramResult {
     if !account_info.is_signer {
         Err(ProgramError::MissingRequiredSignature)
@@ -48,18 +82,262 @@ pub fn assert_owned_by(account: &AccountInfo, owner: &Pubkey) -> ProgramResult {
     }
 }
 
+/// spl-token or Token-2022, banks use the one that owns their base mint
+pub fn is_token_program(key: &Pubkey) -> bool {
+    *key == spl_token::id() || *key == spl_token_2022::id()
+}
+
+/// Unpack a token account of either token program, extensions are left out
+pub fn unpack_token_account(account_info: &AccountInfo) -> Result<TokenAccount, ProgramError> {
+    let data = account_info.try_borrow_data()?;
+    Ok(StateWithExtensions::<TokenAccount>::unpack(&data)?.base)
+}
+
+/// Unpack a mint of either token program, extensions are left out
+pub fn unpack_mint(account_info: &AccountInfo) -> Result<Mint, ProgramError> {
+    let data = account_info.try_borrow_data()?;
+    Ok(StateWithExtensions::<Mint>::unpack(&data)?.base)
+}
+
+/// Size of the vault of a bank of `mint_info`, with room for the account extensions
+/// the mint requires. Fails for mints with extensions a bank can't hold.
+pub fn vault_account_len(mint_info: &AccountInfo) -> Result<usize, ProgramError> {
+    let data = mint_info.try_borrow_data()?;
+    let extension_types = StateWithExtensions::<Mint>::unpack(&data)?.get_extension_types()?;
+    if extension_types
+        .iter()
+        .any(|extension_type| UNSUPPORTED_MINT_EXTENSIONS.contains(extension_type))
+    {
+        return Err(LunabankError::UnsupportedMintExtension.into());
+    }
+    ExtensionType::try_calculate_account_len::<TokenAccount>(
+        &ExtensionType::get_required_init_account_extensions(&extension_types),
+    )
+}
+
+/// Check that a token account holds `mint`, belongs to `owner`, and that nobody
+/// else can move or close it
+pub fn assert_token_account(
+    account: &TokenAccount,
+    mint: &Pubkey,
+    owner: &Pubkey,
+) -> ProgramResult {
+    if account.mint != *mint {
+        return Err(LunabankError::TokenMintMismatch.into());
+    }
+    if account.owner != *owner {
+        return Err(LunabankError::TokenOwnerMismatch.into());
+    }
+    if account.delegate.is_some() {
+        return Err(LunabankError::TokenDelegateNotAllowed.into());
+    }
+    if account.close_authority.is_some() {
+        return Err(LunabankError::TokenCloseAuthorityNotAllowed.into());
+    }
+    Ok(())
+}
+
+/// Check the size and the key byte of account data before it is decoded
+pub fn assert_data_type(data: &[u8], data_type: Key, data_size: usize) -> ProgramResult {
+    if data.is_empty() || data.len() != data_size {
+        return Err(LunabankError::DataSizeMismatch.into());
+    }
+    if data[0] == Key::Uninitialized as u8 {
+        return Err(LunabankError::UninitializedAccount.into());
+    }
+    if data[0] != data_type as u8 {
+        return Err(LunabankError::DataTypeMismatch.into());
+    }
+    Ok(())
+}
+
 pub fn try_from_slice_checked<T: BorshDeserialize>(
     data: &[u8],
     data_type: Key,
     data_size: usize,
 ) -> Result<T, ProgramError> {
-    if (data[0] != data_type as u8 && data[0] != Key::Uninitialized as u8)
-        || data.len() != data_size
-    {
-        return Err(LunabankError::DataTypeMismatch.into());
-    }
+    assert_data_type(data, data_type, data_size)?;
 
-    let result: T = try_from_slice_unchecked(data)?;
+    let result: T = T::try_from_slice(data).map_err(|_| ProgramError::InvalidAccountData)?;
 
     Ok(result)
 }
+
+/// Create a rent exempt account at a PDA, paid by `payer_info`. Lamports sent to the
+/// address beforehand would make `create_account` fail, such an account is topped up,
+/// allocated and assigned instead.
+pub fn create_pda_account<'a>(
+    payer_info: &AccountInfo<'a>,
+    new_account_info: &AccountInfo<'a>,
+    system_program_info: &AccountInfo<'a>,
+    rent: &Rent,
+    size: usize,
+    owner: &Pubkey,
+    signer_seeds: &[&[u8]],
+) -> ProgramResult {
+    let required_lamports = rent.minimum_balance(size).max(1);
+    let current_lamports = new_account_info.lamports();
+    if current_lamports == 0 {
+        return invoke_signed(
+            &system_instruction::create_account(
+                payer_info.key,
+                new_account_info.key,
+                required_lamports,
+                size as u64,
+                owner,
+            ),
+            &[
+                payer_info.clone(),
+                new_account_info.clone(),
+                system_program_info.clone(),
+            ],
+            &[signer_seeds],
+        );
+    }
+
+    if required_lamports > current_lamports {
+        invoke(
+            &system_instruction::transfer(
+                payer_info.key,
+                new_account_info.key,
+                required_lamports - current_lamports,
+            ),
+            &[
+                payer_info.clone(),
+                new_account_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+    }
+    invoke_signed(
+        &system_instruction::allocate(new_account_info.key, size as u64),
+        &[new_account_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )?;
+    invoke_signed(
+        &system_instruction::assign(new_account_info.key, owner),
+        &[new_account_info.clone(), system_program_info.clone()],
+        &[signer_seeds],
+    )
+}
+
+/// Resize a program owned account, topping up rent from `payer_info` when it grows
+/// and returning the surplus to `payer_info` when it shrinks
+pub fn resize_account<'a>(
+    account_info: &AccountInfo<'a>,
+    payer_info: &AccountInfo<'a>,
+    system_program_info: &AccountInfo<'a>,
+    rent: &Rent,
+    new_size: usize,
+) -> ProgramResult {
+    let required_lamports = rent.minimum_balance(new_size).max(1);
+    let current_lamports = account_info.lamports();
+    if required_lamports > current_lamports {
+        invoke(
+            &system_instruction::transfer(
+                payer_info.key,
+                account_info.key,
+                required_lamports - current_lamports,
+            ),
+            &[
+                payer_info.clone(),
+                account_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+    } else if required_lamports < current_lamports {
+        let surplus = current_lamports - required_lamports;
+        **payer_info.try_borrow_mut_lamports()? = payer_info
+            .lamports()
+            .checked_add(surplus)
+            .ok_or(LunabankError::AmountOverflow)?;
+        **account_info.try_borrow_mut_lamports()? = required_lamports;
+    }
+    account_info.realloc(new_size, false)
+}
+
+/// Close a program owned account, its lamports go to `destination_info`
+pub fn close_program_account(
+    account_info: &AccountInfo,
+    destination_info: &AccountInfo,
+) -> ProgramResult {
+    **destination_info.try_borrow_mut_lamports()? = destination_info
+        .lamports()
+        .checked_add(account_info.lamports())
+        .ok_or(LunabankError::AmountOverflow)?;
+    **account_info.try_borrow_mut_lamports()? = 0;
+    *account_info.try_borrow_mut_data()? = &mut [];
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::program_option::COption;
+    use spl_token_2022::state::AccountState;
+
+    fn token_account(mint: Pubkey, owner: Pubkey) -> TokenAccount {
+        TokenAccount {
+            mint,
+            owner,
+            amount: 10,
+            state: AccountState::Initialized,
+            ..TokenAccount::default()
+        }
+    }
+
+    #[test]
+    fn test_assert_token_account() {
+        let mint = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        assert_eq!(
+            assert_token_account(&token_account(mint, owner), &mint, &owner),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_assert_token_account_mint_mismatch() {
+        let mint = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        assert_eq!(
+            assert_token_account(&token_account(Pubkey::new_unique(), owner), &mint, &owner),
+            Err(LunabankError::TokenMintMismatch.into())
+        );
+    }
+
+    #[test]
+    fn test_assert_token_account_owner_mismatch() {
+        let mint = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        assert_eq!(
+            assert_token_account(&token_account(mint, Pubkey::new_unique()), &mint, &owner),
+            Err(LunabankError::TokenOwnerMismatch.into())
+        );
+    }
+
+    #[test]
+    fn test_assert_token_account_delegate() {
+        let mint = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut account = token_account(mint, owner);
+        account.delegate = COption::Some(Pubkey::new_unique());
+        account.delegated_amount = 1;
+        assert_eq!(
+            assert_token_account(&account, &mint, &owner),
+            Err(LunabankError::TokenDelegateNotAllowed.into())
+        );
+    }
+
+    #[test]
+    fn test_assert_token_account_close_authority() {
+        let mint = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut account = token_account(mint, owner);
+        account.close_authority = COption::Some(Pubkey::new_unique());
+        assert_eq!(
+            assert_token_account(&account, &mint, &owner),
+            Err(LunabankError::TokenCloseAuthorityNotAllowed.into())
+        );
+    }
+}
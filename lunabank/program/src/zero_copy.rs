@@ -0,0 +1,351 @@
+//! Zero-copy views over the current (V2) layouts of `Lunabank` and `LunaDeposit`.
+//!
+//! The views borrow the account data in place instead of decoding it, the bytes are
+//! the Borsh encoding of the state structs, so either side can read what the other
+//! wrote. Integers are unaligned in that encoding and are held as little endian byte
+//! arrays. V1 accounts have to go through `Migrate` first.
+//!
+//! `FlashLoan` checks and locks the bank through `LunabankView`, it has no other use for
+//! the state.
+
+use borsh::BorshDeserialize;
+use bytemuck::{Pod, Zeroable};
+use solana_program::{program_error::ProgramError, program_pack::Pack, pubkey::Pubkey};
+
+use crate::{
+    error::LunabankError,
+    state::{Key, LunaDeposit, Lunabank, RecipeMode},
+    utils::assert_data_type,
+};
+
+/// `u64` stored as its 8 little endian bytes, alignment 1
+#[repr(transparent)]
+#[derive(Pod, Zeroable, Clone, Copy, Debug, Default, PartialEq)]
+pub struct PodU64([u8; 8]);
+
+impl From<u64> for PodU64 {
+    fn from(value: u64) -> Self {
+        Self(value.to_le_bytes())
+    }
+}
+
+impl From<PodU64> for u64 {
+    fn from(value: PodU64) -> Self {
+        u64::from_le_bytes(value.0)
+    }
+}
+
+/// `bool` stored as one byte, 0 or 1 like Borsh writes it
+#[repr(transparent)]
+#[derive(Pod, Zeroable, Clone, Copy, Debug, Default, PartialEq)]
+pub struct PodBool(u8);
+
+impl PodBool {
+    fn is_valid(&self) -> bool {
+        self.0 <= 1
+    }
+}
+
+impl From<bool> for PodBool {
+    fn from(value: bool) -> Self {
+        Self(value as u8)
+    }
+}
+
+impl From<PodBool> for bool {
+    fn from(value: PodBool) -> Self {
+        value.0 != 0
+    }
+}
+
+#[repr(C)]
+#[derive(Pod, Zeroable, Clone, Copy, Debug, PartialEq)]
+pub struct LunabankView {
+    pub key: u8,
+    pub owner: Pubkey,
+    pub base_token_mint: Pubkey,
+    pub recipe_token_mint: Pubkey,
+    pub base_token_account: Pubkey,
+    pub coef: PodU64,
+    pub is_shutdown: PodBool,
+    pub active_deposits: PodU64,
+    pub bank_id: PodU64,
+    pub pda_bump_seed: u8,
+    /// `RecipeMode` discriminant
+    pub recipe_mode: u8,
+    pub token_program: Pubkey,
+    pub is_locked: PodBool,
+    pub asset_count: PodU64,
+    pub reserved: [u8; 22],
+}
+
+impl LunabankView {
+    /// Borrow a bank from account data, checked like `Lunabank::unpack_from_slice`
+    pub fn load(data: &[u8]) -> Result<&Self, ProgramError> {
+        if data.first() == Some(&(Key::LunabankV1 as u8)) {
+            return Err(LunabankError::AccountNotMigrated.into());
+        }
+        assert_data_type(data, Key::LunabankV2, Lunabank::LEN)?;
+        let view: &Self = bytemuck::from_bytes(data);
+        if !view.is_shutdown.is_valid() || !view.is_locked.is_valid() || view.recipe_mode().is_err()
+        {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(view)
+    }
+
+    pub fn load_mut(data: &mut [u8]) -> Result<&mut Self, ProgramError> {
+        Self::load(data)?;
+        Ok(bytemuck::from_bytes_mut(data))
+    }
+
+    pub fn recipe_mode(&self) -> Result<RecipeMode, ProgramError> {
+        RecipeMode::try_from_slice(&[self.recipe_mode])
+            .map_err(|_| ProgramError::InvalidAccountData)
+    }
+
+    /// Decode the viewed bytes into the Borsh struct
+    pub fn to_state(&self) -> Result<Lunabank, ProgramError> {
+        Lunabank::unpack_from_slice(bytemuck::bytes_of(self))
+    }
+}
+
+impl From<&Lunabank> for LunabankView {
+    fn from(bank: &Lunabank) -> Self {
+        let mut view = Self::zeroed();
+        bank.pack_into_slice(bytemuck::bytes_of_mut(&mut view));
+        view
+    }
+}
+
+#[repr(C)]
+#[derive(Pod, Zeroable, Clone, Copy, Debug, PartialEq)]
+pub struct LunaDepositView {
+    pub key: u8,
+    pub owner: Pubkey,
+    pub amount: PodU64,
+    pub start_timestamp: PodU64,
+    pub end_timestamp: PodU64,
+    pub bump_seed: u8,
+    pub bank: Pubkey,
+    pub base_token_mint: Pubkey,
+    pub reserved: [u8; 32],
+}
+
+impl LunaDepositView {
+    /// Borrow a deposit from account data, checked like `LunaDeposit::unpack_from_slice`
+    pub fn load(data: &[u8]) -> Result<&Self, ProgramError> {
+        if data.first() == Some(&(Key::LunaDepositV1 as u8))
+            || data.first() == Some(&(Key::LunaDepositV2 as u8))
+        {
+            return Err(LunabankError::AccountNotMigrated.into());
+        }
+        assert_data_type(data, Key::LunaDepositV3, LunaDeposit::LEN)?;
+        Ok(bytemuck::from_bytes(data))
+    }
+
+    pub fn load_mut(data: &mut [u8]) -> Result<&mut Self, ProgramError> {
+        Self::load(data)?;
+        Ok(bytemuck::from_bytes_mut(data))
+    }
+
+    /// Decode the viewed bytes into the Borsh struct
+    pub fn to_state(&self) -> Result<LunaDeposit, ProgramError> {
+        LunaDeposit::unpack_from_slice(bytemuck::bytes_of(self))
+    }
+}
+
+impl From<&LunaDeposit> for LunaDepositView {
+    fn from(deposit: &LunaDeposit) -> Self {
+        let mut view = Self::zeroed();
+        deposit.pack_into_slice(bytemuck::bytes_of_mut(&mut view));
+        view
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem::size_of;
+
+    fn bank() -> Lunabank {
+        Lunabank {
+            key: Key::LunabankV2,
+            owner: Pubkey::new_from_array([1; 32]),
+            base_token_mint: Pubkey::new_from_array([2; 32]),
+            recipe_token_mint: Pubkey::new_from_array([3; 32]),
+            base_token_account: Pubkey::new_from_array([4; 32]),
+            coef: 0x0102_0304_0506_0708,
+            is_shutdown: true,
+            active_deposits: 9,
+            bank_id: 0x0a0b,
+            pda_bump_seed: 254,
+            recipe_mode: RecipeMode::NonTransferable,
+            token_program: Pubkey::new_from_array([8; 32]),
+            is_locked: true,
+            asset_count: 0x0c,
+            reserved: [0; 22],
+        }
+    }
+
+    fn deposit() -> LunaDeposit {
+        LunaDeposit {
+            key: Key::LunaDepositV3,
+            owner: Pubkey::new_from_array([5; 32]),
+            amount: 100,
+            start_timestamp: 0x6000_0000,
+            end_timestamp: 0x6000_0064,
+            bump_seed: 253,
+            bank: Pubkey::new_from_array([6; 32]),
+            base_token_mint: Pubkey::new_from_array([7; 32]),
+            reserved: [0; 32],
+        }
+    }
+
+    fn golden_bank() -> Vec<u8> {
+        let mut data = vec![4]; // key
+        data.extend([1; 32]); // owner
+        data.extend([2; 32]); // base_token_mint
+        data.extend([3; 32]); // recipe_token_mint
+        data.extend([4; 32]); // base_token_account
+        data.extend([8, 7, 6, 5, 4, 3, 2, 1]); // coef
+        data.extend([1]); // is_shutdown
+        data.extend([9, 0, 0, 0, 0, 0, 0, 0]); // active_deposits
+        data.extend([0x0b, 0x0a, 0, 0, 0, 0, 0, 0]); // bank_id
+        data.extend([254]); // pda_bump_seed
+        data.extend([1]); // recipe_mode
+        data.extend([8; 32]); // token_program
+        data.extend([1]); // is_locked
+        data.extend([0x0c, 0, 0, 0, 0, 0, 0, 0]); // asset_count
+        data.extend([0; 22]); // reserved
+        data
+    }
+
+    fn golden_deposit() -> Vec<u8> {
+        let mut data = vec![7]; // key
+        data.extend([5; 32]); // owner
+        data.extend([100, 0, 0, 0, 0, 0, 0, 0]); // amount
+        data.extend([0, 0, 0, 0x60, 0, 0, 0, 0]); // start_timestamp
+        data.extend([0x64, 0, 0, 0x60, 0, 0, 0, 0]); // end_timestamp
+        data.extend([253]); // bump_seed
+        data.extend([6; 32]); // bank
+        data.extend([7; 32]); // base_token_mint
+        data.extend([0; 32]); // reserved
+        data
+    }
+
+    #[test]
+    fn test_sizes() {
+        assert_eq!(size_of::<LunabankView>(), Lunabank::LEN);
+        assert_eq!(size_of::<LunaDepositView>(), LunaDeposit::LEN);
+    }
+
+    #[test]
+    fn test_bank_golden_layout() {
+        let golden = golden_bank();
+        let mut packed = vec![0; Lunabank::LEN];
+        Lunabank::pack(bank(), &mut packed).unwrap();
+        assert_eq!(packed, golden);
+
+        let view = LunabankView::load(&golden).unwrap();
+        assert_eq!(view.owner, bank().owner);
+        assert_eq!(u64::from(view.coef), bank().coef);
+        assert!(bool::from(view.is_shutdown));
+        assert_eq!(u64::from(view.active_deposits), 9);
+        assert_eq!(u64::from(view.bank_id), 0x0a0b);
+        assert_eq!(view.pda_bump_seed, 254);
+        assert_eq!(view.recipe_mode(), Ok(RecipeMode::NonTransferable));
+        assert_eq!(view.token_program, bank().token_program);
+        assert!(bool::from(view.is_locked));
+        assert_eq!(u64::from(view.asset_count), 0x0c);
+        assert_eq!(view.to_state(), Ok(bank()));
+        assert_eq!(
+            bytemuck::bytes_of(&LunabankView::from(&bank())),
+            &golden[..]
+        );
+    }
+
+    #[test]
+    fn test_deposit_golden_layout() {
+        let golden = golden_deposit();
+        let mut packed = vec![0; LunaDeposit::LEN];
+        LunaDeposit::pack(deposit(), &mut packed).unwrap();
+        assert_eq!(packed, golden);
+
+        let view = LunaDepositView::load(&golden).unwrap();
+        assert_eq!(view.owner, deposit().owner);
+        assert_eq!(u64::from(view.amount), 100);
+        assert_eq!(u64::from(view.start_timestamp), 0x6000_0000);
+        assert_eq!(u64::from(view.end_timestamp), 0x6000_0064);
+        assert_eq!(view.bump_seed, 253);
+        assert_eq!(view.bank, deposit().bank);
+        assert_eq!(view.base_token_mint, deposit().base_token_mint);
+        assert_eq!(view.to_state(), Ok(deposit()));
+        assert_eq!(
+            bytemuck::bytes_of(&LunaDepositView::from(&deposit())),
+            &golden[..]
+        );
+    }
+
+    #[test]
+    fn test_load_mut_writes_through() {
+        let mut data = golden_bank();
+        let view = LunabankView::load_mut(&mut data).unwrap();
+        view.active_deposits = 10.into();
+        view.is_shutdown = false.into();
+
+        let mut expected = bank();
+        expected.active_deposits = 10;
+        expected.is_shutdown = false;
+        assert_eq!(Lunabank::unpack(&data), Ok(expected));
+    }
+
+    #[test]
+    fn test_load_checks() {
+        let data = golden_bank();
+        assert_eq!(
+            LunabankView::load(&data[..Lunabank::LEN - 1]).err(),
+            Some(LunabankError::DataSizeMismatch.into())
+        );
+        assert_eq!(
+            LunabankView::load(&[0; Lunabank::LEN]).err(),
+            Some(LunabankError::UninitializedAccount.into())
+        );
+        assert_eq!(
+            LunaDepositView::load(&data).err(),
+            Some(LunabankError::DataSizeMismatch.into())
+        );
+
+        // V1 accounts are migrated before they can be viewed
+        let mut v1 = data.clone();
+        v1[0] = Key::LunabankV1 as u8;
+        assert_eq!(
+            LunabankView::load(&v1).err(),
+            Some(LunabankError::AccountNotMigrated.into())
+        );
+        let mut v2 = vec![0; LunaDeposit::LEN_V2];
+        v2[0] = Key::LunaDepositV2 as u8;
+        assert_eq!(
+            LunaDepositView::load(&v2).err(),
+            Some(LunabankError::AccountNotMigrated.into())
+        );
+
+        // Borsh only decodes 0 and 1 as bool, and known enum discriminants
+        for offset in [
+            Lunabank::IS_SHUTDOWN_OFFSET,
+            Lunabank::RECIPE_MODE_OFFSET,
+            Lunabank::IS_LOCKED_OFFSET,
+        ] {
+            let mut invalid = data.clone();
+            invalid[offset] = 0xff;
+            assert_eq!(
+                LunabankView::load(&invalid).err(),
+                Some(ProgramError::InvalidAccountData)
+            );
+            assert_eq!(
+                Lunabank::unpack(&invalid),
+                Err(ProgramError::InvalidAccountData)
+            );
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//! Flash loan borrower for the program-test suites. It pays the loan and the fee back out
+//! of its token account, or as much of them as the account holds. Given a program and the
+//! accounts of a `FlashLoan` after its own, it first calls back into that program for another
+//! loan of the same amount, the way a reentrancy attack would.
+
+use borsh::BorshSerialize;
+use lunabank::instruction::LunabankInstruction;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program::invoke,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+use spl_token_2022::{
+    extension::StateWithExtensions,
+    state::{Account as TokenAccount, Mint},
+};
+
+solana_program::declare_id!("ETiv7zqwkDpaFmAEteF3uhgLSJYyDcxiJajr6mAMTd85");
+
+/// Accounts the borrower expects after the ones of `FlashLoan`
+pub fn account_metas(
+    token_program: &Pubkey,
+    owner: &Pubkey,
+    token_account: &Pubkey,
+    base_token_mint: &Pubkey,
+    vault: &Pubkey,
+) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(*token_account, false),
+        AccountMeta::new_readonly(*base_token_mint, false),
+        AccountMeta::new(*vault, false),
+        AccountMeta::new_readonly(*owner, true),
+        AccountMeta::new_readonly(*token_program, false),
+    ]
+}
+
+/// Accounts the borrower expects to call back into `program_id` with a `FlashLoan` of
+/// `flash_loan_accounts`, after the ones of `account_metas`
+pub fn reentry_account_metas(
+    program_id: &Pubkey,
+    flash_loan_accounts: Vec<AccountMeta>,
+) -> Vec<AccountMeta> {
+    let mut metas = vec![AccountMeta::new_readonly(*program_id, false)];
+    metas.extend(flash_loan_accounts);
+    metas
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64, ProgramError> {
+    data.get(offset..offset + 8)
+        .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ProgramError::InvalidInstructionData)
+}
+
+pub fn process(_program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let token_account = next_account_info(accounts_iter)?;
+    let base_token_mint = next_account_info(accounts_iter)?;
+    let vault = next_account_info(accounts_iter)?;
+    let owner = next_account_info(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+
+    let amount = read_u64(data, 0)?;
+    if let Some(program) = accounts_iter.next() {
+        let reentry_accounts = accounts_iter.as_slice();
+        let reentry_ix = Instruction {
+            program_id: *program.key,
+            accounts: reentry_accounts
+                .iter()
+                .map(|info| AccountMeta {
+                    pubkey: *info.key,
+                    is_signer: info.is_signer,
+                    is_writable: info.is_writable,
+                })
+                .collect(),
+            data: LunabankInstruction::FlashLoan { amount }.try_to_vec()?,
+        };
+        let mut reentry_infos = reentry_accounts.to_vec();
+        reentry_infos.push(program.clone());
+        invoke(&reentry_ix, &reentry_infos)?;
+    }
+
+    let owed = amount
+        .checked_add(read_u64(data, 8)?)
+        .ok_or(ProgramError::InvalidInstructionData)?;
+    let balance = StateWithExtensions::<TokenAccount>::unpack(&token_account.data.borrow())?
+        .base
+        .amount;
+    let decimals = StateWithExtensions::<Mint>::unpack(&base_token_mint.data.borrow())?
+        .base
+        .decimals;
+
+    invoke(
+        &spl_token_2022::instruction::transfer_checked(
+            token_program.key,
+            token_account.key,
+            base_token_mint.key,
+            vault.key,
+            owner.key,
+            &[],
+            owed.min(balance),
+            decimals,
+        )?,
+        &[
+            token_account.clone(),
+            base_token_mint.clone(),
+            vault.clone(),
+            owner.clone(),
+            token_program.clone(),
+        ],
+    )
+}
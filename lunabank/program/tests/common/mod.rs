@@ -0,0 +1,351 @@
+//! Helpers shared by the program-test suites
+#![allow(dead_code)]
+
+pub mod borrower;
+
+use lunabank::{
+    instruction,
+    processor::Processor,
+    state::{
+        find_bank_address, find_recipe_mint_address, find_vault_address, Lunabank, RecipeMode,
+    },
+};
+use solana_program::{
+    clock::Clock,
+    instruction::{Instruction, InstructionError},
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    system_instruction,
+};
+use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use spl_token_2022::{
+    extension::{BaseStateWithExtensions, ExtensionType, StateWithExtensions},
+    state::{Account as TokenAccount, Mint},
+};
+
+pub struct Bank {
+    pub program_id: Pubkey,
+    pub token_program: Pubkey,
+    pub base_mint: Keypair,
+    pub config: Pubkey,
+    pub vault: Pubkey,
+    pub recipe_mint: Pubkey,
+}
+
+pub struct User {
+    pub wallet: Keypair,
+    pub base: Pubkey,
+    pub recipe: Pubkey,
+}
+
+pub async fn process(
+    context: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> Result<(), TransactionError> {
+    let recent_blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+    let transaction = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        recent_blockhash,
+    );
+    context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .map_err(|e| e.unwrap())
+}
+
+/// Like `process`, also returning the log messages of the transaction
+pub async fn process_with_logs(
+    context: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> (Result<(), TransactionError>, Vec<String>) {
+    let recent_blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+    let transaction = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        recent_blockhash,
+    );
+    let result = context
+        .banks_client
+        .process_transaction_with_metadata(transaction)
+        .await
+        .unwrap();
+    (result.result, result.metadata.unwrap().log_messages)
+}
+
+/// `Program log: ` lines written by `program_id` itself, not by the programs it invokes
+pub fn program_logs(program_id: &Pubkey, logs: &[String]) -> Vec<String> {
+    let program_id = program_id.to_string();
+    let mut stack = vec![];
+    let mut own = vec![];
+    for line in logs {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["Program", "log:", ..] if stack.last() == Some(&program_id.as_str()) => {
+                own.push(line.clone())
+            }
+            ["Program", id, "invoke", ..] => stack.push(*id),
+            ["Program", _, "success"] | ["Program", _, "failed:", ..] => {
+                stack.pop();
+            }
+            _ => {}
+        }
+    }
+    own
+}
+
+pub fn assert_error<E: Into<ProgramError>>(result: Result<(), TransactionError>, error: E) {
+    assert_eq!(
+        result.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::from(u64::from(error.into())))
+    );
+}
+
+/// A token account of `mint`, in the token program of the mint and with room for the
+/// account extensions it requires. Token-2022 accounts get an immutable owner like
+/// associated token accounts, non-transferable mints only mint to those.
+pub async fn create_token_account(
+    context: &mut ProgramTestContext,
+    mint: &Pubkey,
+    owner: &Pubkey,
+) -> Pubkey {
+    let mint_account = context
+        .banks_client
+        .get_account(*mint)
+        .await
+        .unwrap()
+        .unwrap();
+    let extension_types = StateWithExtensions::<Mint>::unpack(&mint_account.data)
+        .unwrap()
+        .get_extension_types()
+        .unwrap();
+    let mut account_extensions =
+        ExtensionType::get_required_init_account_extensions(&extension_types);
+    let immutable_owner = mint_account.owner == spl_token_2022::id();
+    if immutable_owner {
+        account_extensions.push(ExtensionType::ImmutableOwner);
+    }
+    let len =
+        ExtensionType::try_calculate_account_len::<TokenAccount>(&account_extensions).unwrap();
+
+    let account = Keypair::new();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let mut instructions = vec![system_instruction::create_account(
+        &context.payer.pubkey(),
+        &account.pubkey(),
+        rent.minimum_balance(len),
+        len as u64,
+        &mint_account.owner,
+    )];
+    if immutable_owner {
+        instructions.push(
+            spl_token_2022::instruction::initialize_immutable_owner(
+                &mint_account.owner,
+                &account.pubkey(),
+            )
+            .unwrap(),
+        );
+    }
+    instructions.push(
+        spl_token_2022::instruction::initialize_account(
+            &mint_account.owner,
+            &account.pubkey(),
+            mint,
+            owner,
+        )
+        .unwrap(),
+    );
+    process(context, &instructions, &[&account]).await.unwrap();
+    account.pubkey()
+}
+
+pub async fn create_mint(context: &mut ProgramTestContext) -> Keypair {
+    create_mint_with(context, Keypair::new(), &spl_token::id(), &[], vec![]).await
+}
+
+/// A mint of `token_program` with the payer as mint authority. `extensions` initialize
+/// the `extension_types` of the mint.
+pub async fn create_mint_with(
+    context: &mut ProgramTestContext,
+    mint: Keypair,
+    token_program: &Pubkey,
+    extension_types: &[ExtensionType],
+    extensions: Vec<Instruction>,
+) -> Keypair {
+    let len = ExtensionType::try_calculate_account_len::<Mint>(extension_types).unwrap();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let mut instructions = vec![system_instruction::create_account(
+        &context.payer.pubkey(),
+        &mint.pubkey(),
+        rent.minimum_balance(len),
+        len as u64,
+        token_program,
+    )];
+    instructions.extend(extensions);
+    instructions.push(
+        spl_token_2022::instruction::initialize_mint(
+            token_program,
+            &mint.pubkey(),
+            &context.payer.pubkey(),
+            None,
+            6,
+        )
+        .unwrap(),
+    );
+    process(context, &instructions, &[&mint]).await.unwrap();
+    mint
+}
+
+/// Starts program-test with the program loaded at a fresh address, next to the flash loan
+/// borrower
+pub async fn start() -> (ProgramTestContext, Pubkey) {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = ProgramTest::new("lunabank", program_id, processor!(Processor::process));
+    program_test.add_program("borrower", borrower::id(), processor!(borrower::process));
+    let context = program_test.start_with_context().await;
+    (context, program_id)
+}
+
+/// Starts the test bank with id 0, owned by the context payer
+pub async fn setup() -> (ProgramTestContext, Bank) {
+    setup_with(&spl_token::id(), RecipeMode::Frozen).await
+}
+
+/// Same as `setup`, with a base mint of `token_program` and the given recipe mode
+pub async fn setup_with(
+    token_program: &Pubkey,
+    recipe_mode: RecipeMode,
+) -> (ProgramTestContext, Bank) {
+    let (mut context, program_id) = start().await;
+
+    let base_mint =
+        create_mint_with(&mut context, Keypair::new(), token_program, &[], vec![]).await;
+    let (config, _) = find_bank_address(&program_id, &base_mint.pubkey(), 0);
+    let ix = instruction::init_with_options(
+        &program_id,
+        token_program,
+        &context.payer.pubkey(),
+        &base_mint.pubkey(),
+        0,
+        recipe_mode,
+    );
+    process(&mut context, &[ix], &[]).await.unwrap();
+
+    let bank = Bank {
+        program_id,
+        token_program: *token_program,
+        base_mint,
+        config,
+        vault: find_vault_address(&program_id, &config).0,
+        recipe_mint: find_recipe_mint_address(&program_id, &config).0,
+    };
+    (context, bank)
+}
+
+/// A wallet holding 1 SOL
+pub async fn create_wallet(context: &mut ProgramTestContext) -> Keypair {
+    let wallet = Keypair::new();
+    fund_wallet(context, &wallet).await;
+    wallet
+}
+
+async fn fund_wallet(context: &mut ProgramTestContext, wallet: &Keypair) {
+    process(
+        context,
+        &[system_instruction::transfer(
+            &context.payer.pubkey(),
+            &wallet.pubkey(),
+            1_000_000_000,
+        )],
+        &[],
+    )
+    .await
+    .unwrap();
+}
+
+/// A funded wallet with `amount` base tokens and an empty recipe token account
+pub async fn create_user(context: &mut ProgramTestContext, bank: &Bank, amount: u64) -> User {
+    create_user_with(context, bank, Keypair::new(), amount).await
+}
+
+/// Same as `create_user` with the given wallet
+pub async fn create_user_with(
+    context: &mut ProgramTestContext,
+    bank: &Bank,
+    wallet: Keypair,
+    amount: u64,
+) -> User {
+    fund_wallet(context, &wallet).await;
+    let base = create_token_account(context, &bank.base_mint.pubkey(), &wallet.pubkey()).await;
+    let recipe = create_token_account(context, &bank.recipe_mint, &wallet.pubkey()).await;
+    process(
+        context,
+        &[spl_token_2022::instruction::mint_to(
+            &bank.token_program,
+            &bank.base_mint.pubkey(),
+            &base,
+            &context.payer.pubkey(),
+            &[],
+            amount,
+        )
+        .unwrap()],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    User {
+        wallet,
+        base,
+        recipe,
+    }
+}
+
+pub async fn token_account(
+    context: &mut ProgramTestContext,
+    address: &Pubkey,
+) -> Option<TokenAccount> {
+    context
+        .banks_client
+        .get_account(*address)
+        .await
+        .unwrap()
+        .map(|account| {
+            StateWithExtensions::<TokenAccount>::unpack(&account.data)
+                .unwrap()
+                .base
+        })
+}
+
+pub async fn bank_state(context: &mut ProgramTestContext, bank: &Bank) -> Lunabank {
+    let account = context
+        .banks_client
+        .get_account(bank.config)
+        .await
+        .unwrap()
+        .unwrap();
+    Lunabank::unpack_from_slice(&account.data).unwrap()
+}
+
+/// Moves the clock `seconds` forward, on a new slot so retried transactions get a fresh blockhash
+pub async fn warp_clock(context: &mut ProgramTestContext, seconds: u64) {
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    context.warp_to_slot(clock.slot + 2).unwrap();
+    clock = context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp += seconds as i64;
+    context.set_sysvar(&clock);
+}
@@ -0,0 +1,185 @@
+//! Compute units of the hot paths. The stored bump seeds are measured on the BPF build of the
+//! program, run with `cargo test-bpf`. The logs test runs the program natively, where syscalls
+//! are not metered, so the logs it writes are priced with the `ComputeBudget` of the runtime.
+mod common;
+
+#[cfg(feature = "test-bpf")]
+use common::create_user_with;
+use common::{create_mint_with, create_user, process, process_with_logs, program_logs, Bank, User};
+#[cfg(feature = "test-bpf")]
+use lunabank::state::{find_bank_authority_address, find_deposit_address};
+use lunabank::{
+    instruction,
+    processor::LoggingProcessor,
+    state::{find_bank_address, find_recipe_mint_address, find_vault_address},
+};
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+use solana_program_runtime::compute_budget::ComputeBudget;
+use solana_program_test::{processor, tokio, ProgramTest, ProgramTestContext};
+use solana_sdk::signature::{Keypair, Signer};
+#[cfg(feature = "test-bpf")]
+use solana_sdk::transaction::Transaction;
+
+/// Initializes the bank with id 0 of `base_mint` in the program at `program_id`
+async fn init_bank(
+    context: &mut ProgramTestContext,
+    program_id: &Pubkey,
+    base_mint: Keypair,
+) -> Bank {
+    let base_mint = create_mint_with(context, base_mint, &spl_token::id(), &[], vec![]).await;
+    let ix = instruction::init(
+        program_id,
+        &spl_token::id(),
+        &context.payer.pubkey(),
+        &base_mint.pubkey(),
+        0,
+    );
+    process(context, &[ix], &[]).await.unwrap();
+    let config = find_bank_address(program_id, &base_mint.pubkey(), 0).0;
+    Bank {
+        program_id: *program_id,
+        token_program: spl_token::id(),
+        base_mint,
+        config,
+        vault: find_vault_address(program_id, &config).0,
+        recipe_mint: find_recipe_mint_address(program_id, &config).0,
+    }
+}
+
+fn deposit_ix(bank: &Bank, user: &User) -> Instruction {
+    instruction::deposit(
+        &bank.program_id,
+        &bank.token_program,
+        &user.wallet.pubkey(),
+        &bank.config,
+        &bank.base_mint.pubkey(),
+        &user.base,
+        &user.recipe,
+        100,
+        0,
+    )
+}
+
+fn withdraw_ix(bank: &Bank, user: &User) -> Instruction {
+    instruction::withdraw(
+        &bank.program_id,
+        &bank.token_program,
+        &user.wallet.pubkey(),
+        &bank.config,
+        &bank.base_mint.pubkey(),
+        &user.base,
+        &user.recipe,
+    )
+}
+
+/// Units charged for the logs written by the program of `bank` in a deposit and a withdraw,
+/// each `sol_log` costs the syscall base cost or the length of the message if longer
+async fn deposit_withdraw_log_units(
+    context: &mut ProgramTestContext,
+    budget: &ComputeBudget,
+    bank: &Bank,
+) -> u64 {
+    let user = create_user(context, bank, 100).await;
+    let mut units = 0;
+    for ix in [deposit_ix(bank, &user), withdraw_ix(bank, &user)] {
+        let (result, logs) = process_with_logs(context, &[ix], &[&user.wallet]).await;
+        result.unwrap();
+        for line in program_logs(&bank.program_id, &logs) {
+            let message = line.strip_prefix("Program log: ").unwrap();
+            units += budget.syscall_base_cost.max(message.len() as u64);
+        }
+    }
+    units
+}
+
+/// Units consumed by a withdraw of a fresh deposit of `wallet` in `bank`
+#[cfg(feature = "test-bpf")]
+async fn withdraw_units(context: &mut ProgramTestContext, bank: &Bank, wallet: Keypair) -> u64 {
+    let user = create_user_with(context, bank, wallet, 100).await;
+    let ix = deposit_ix(bank, &user);
+    process(context, &[ix], &[&user.wallet]).await.unwrap();
+
+    let recent_blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+    let transaction = Transaction::new_signed_with_payer(
+        &[withdraw_ix(bank, &user)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &user.wallet],
+        recent_blockhash,
+    );
+    let result = context
+        .banks_client
+        .process_transaction_with_metadata(transaction)
+        .await
+        .unwrap();
+    result.result.unwrap();
+    result.metadata.unwrap().compute_units_consumed
+}
+
+#[cfg(feature = "test-bpf")]
+#[tokio::test]
+async fn test_stored_bump_compute_units() {
+    let budget = ComputeBudget::default();
+    let program_id = Pubkey::new_unique();
+    let mut context = ProgramTest::new("lunabank", program_id, None)
+        .start_with_context()
+        .await;
+
+    // withdraw checks the bank authority and the deposit account, first with both at the
+    // bump `find_program_address` tries first, then with both a few bumps further down
+    let mut units = vec![];
+    for bumps in [u8::MAX..=u8::MAX, 0..=u8::MAX - 4] {
+        let base_mint = loop {
+            let base_mint = Keypair::new();
+            let (config, _) = find_bank_address(&program_id, &base_mint.pubkey(), 0);
+            if bumps.contains(&find_bank_authority_address(&program_id, &config).1) {
+                break base_mint;
+            }
+        };
+        let bank = init_bank(&mut context, &program_id, base_mint).await;
+        let wallet = loop {
+            let wallet = Keypair::new();
+            let (_, bump) = find_deposit_address(&program_id, &bank.config, &wallet.pubkey());
+            if bumps.contains(&bump) {
+                break wallet;
+            }
+        };
+        units.push(withdraw_units(&mut context, &bank, wallet).await);
+    }
+
+    // searching for the bumps would cost at least 8 more hashes in the second withdraw
+    assert!(
+        units[0].abs_diff(units[1]) < budget.create_program_address_units,
+        "withdraws consumed {} and {} units",
+        units[0],
+        units[1]
+    );
+}
+
+#[tokio::test]
+async fn test_verbose_logs_compute_units() {
+    let budget = ComputeBudget::default();
+    let quiet_program_id = Pubkey::new_unique();
+    let verbose_program_id = Pubkey::new_unique();
+    let mut program_test = ProgramTest::new(
+        "lunabank",
+        quiet_program_id,
+        processor!(LoggingProcessor::<false>::process),
+    );
+    program_test.add_program(
+        "lunabank_verbose",
+        verbose_program_id,
+        processor!(LoggingProcessor::<true>::process),
+    );
+    let mut context = program_test.start_with_context().await;
+
+    let quiet_bank = init_bank(&mut context, &quiet_program_id, Keypair::new()).await;
+    let verbose_bank = init_bank(&mut context, &verbose_program_id, Keypair::new()).await;
+    let quiet_units = deposit_withdraw_log_units(&mut context, &budget, &quiet_bank).await;
+    let verbose_units = deposit_withdraw_log_units(&mut context, &budget, &verbose_bank).await;
+    assert!(
+        verbose_units > quiet_units,
+        "verbose logs cost {} units, quiet logs {}",
+        verbose_units,
+        quiet_units
+    );
+}
@@ -0,0 +1,249 @@
+// Generates `idl/lunabank.json` from the Borsh schemas of the instruction and state
+// types and the account specs of every instruction. Fails when the checked in file is
+// out of date, `LUNABANK_UPDATE_IDL=1 cargo test --test idl` rewrites it.
+use borsh::{
+    schema::{BorshSchemaContainer, Declaration, Definition, Fields},
+    BorshSchema,
+};
+use lunabank::{
+    accounts::{
+        AccountOwner, AccountSpec, AddAssetAccounts, CloseAccounts, DepositAccounts,
+        FlashLoanAccounts, InitAccounts, MigrateAccounts, RemoveAssetAccounts, ShutdownAccounts,
+        WithdrawAccounts, ASSET,
+    },
+    instruction::LunabankInstruction,
+    state::{Key, LunaAsset, LunaDeposit, LunaRegistryEntry, Lunabank, RecipeMode},
+};
+use serde_json::{json, Value};
+use solana_program::program_pack::Pack;
+use std::{env, fs};
+
+const IDL_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/idl/lunabank.json");
+
+fn instruction_accounts(variant: &str) -> Vec<AccountSpec> {
+    match variant {
+        "Init" => InitAccounts::SPECS.to_vec(),
+        "Close" => CloseAccounts::SPECS.to_vec(),
+        "DepositInstruction" => DepositAccounts::SPECS.to_vec(),
+        "WithdrawInstruction" => WithdrawAccounts::SPECS.to_vec(),
+        "Shutdown" => ShutdownAccounts::SPECS.to_vec(),
+        "Migrate" => MigrateAccounts::SPECS.to_vec(),
+        "InitWithOptions" => InitAccounts::SPECS.to_vec(),
+        "AddAsset" => AddAssetAccounts::SPECS.to_vec(),
+        "DepositAsset" => [DepositAccounts::SPECS, &[ASSET]].concat(),
+        "WithdrawAsset" => [WithdrawAccounts::SPECS, &[ASSET]].concat(),
+        "FlashLoan" => FlashLoanAccounts::SPECS.to_vec(),
+        "RemoveAsset" => RemoveAssetAccounts::SPECS.to_vec(),
+        _ => panic!("no account list for instruction {}", variant),
+    }
+}
+
+fn definition<'a>(schema: &'a BorshSchemaContainer, declaration: &str) -> Option<&'a Definition> {
+    schema.definitions.get(declaration)
+}
+
+fn named_fields<'a>(
+    schema: &'a BorshSchemaContainer,
+    declaration: &str,
+) -> &'a [(String, Declaration)] {
+    match definition(schema, declaration) {
+        Some(Definition::Struct {
+            fields: Fields::NamedFields(fields),
+        }) => fields,
+        Some(Definition::Struct {
+            fields: Fields::Empty,
+        }) => &[],
+        other => panic!("{} is not a struct: {:?}", declaration, other),
+    }
+}
+
+fn type_json(schema: &BorshSchemaContainer, declaration: &str) -> Value {
+    if declaration == "Pubkey" {
+        return json!("publicKey");
+    }
+    match definition(schema, declaration) {
+        None => json!(declaration),
+        Some(Definition::Array { length, elements }) => {
+            json!({ "array": [type_json(schema, elements), length] })
+        }
+        Some(Definition::Sequence { elements }) => json!({ "vec": type_json(schema, elements) }),
+        Some(_) => json!({ "defined": declaration }),
+    }
+}
+
+/// Encoded size, `None` for types of variable length
+fn fixed_size(schema: &BorshSchemaContainer, declaration: &str) -> Option<usize> {
+    match definition(schema, declaration) {
+        None => Some(match declaration {
+            "u8" | "i8" | "bool" => 1,
+            "u16" | "i16" => 2,
+            "u32" | "i32" => 4,
+            "u64" | "i64" => 8,
+            "u128" | "i128" => 16,
+            _ => return None,
+        }),
+        Some(Definition::Array { length, elements }) => {
+            Some(*length as usize * fixed_size(schema, elements)?)
+        }
+        Some(Definition::Sequence { .. }) => None,
+        Some(Definition::Tuple { elements }) => elements
+            .iter()
+            .map(|element| fixed_size(schema, element))
+            .sum(),
+        Some(Definition::Enum { variants }) => {
+            // a one byte tag, only unit variants have a fixed size
+            if variants
+                .iter()
+                .all(|(_, variant)| named_fields(schema, variant).is_empty())
+            {
+                Some(1)
+            } else {
+                None
+            }
+        }
+        Some(Definition::Struct { fields }) => match fields {
+            Fields::NamedFields(fields) => fields
+                .iter()
+                .map(|(_, field)| fixed_size(schema, field))
+                .sum(),
+            Fields::UnnamedFields(fields) => {
+                fields.iter().map(|field| fixed_size(schema, field)).sum()
+            }
+            Fields::Empty => Some(0),
+        },
+    }
+}
+
+fn account_spec_json(spec: &AccountSpec) -> Value {
+    json!({
+        "name": spec.name,
+        "isSigner": spec.is_signer,
+        "isWritable": spec.is_writable,
+        "owner": match spec.owner {
+            AccountOwner::Any => Value::Null,
+            AccountOwner::Program => json!("program"),
+            AccountOwner::Token => json!("token"),
+        },
+        "address": match (spec.address, spec.one_of) {
+            (Some(address), _) => json!(address.to_string()),
+            (None, []) => Value::Null,
+            (None, addresses) => json!(addresses
+                .iter()
+                .map(|address| address.to_string())
+                .collect::<Vec<_>>()),
+        },
+    })
+}
+
+fn instructions_json() -> Value {
+    let schema = LunabankInstruction::schema_container();
+    let variants = match definition(&schema, &schema.declaration) {
+        Some(Definition::Enum { variants }) => variants,
+        other => panic!("LunabankInstruction is not an enum: {:?}", other),
+    };
+    variants
+        .iter()
+        .enumerate()
+        .map(|(discriminant, (name, declaration))| {
+            let args: Vec<Value> = named_fields(&schema, declaration)
+                .iter()
+                .map(|(name, field)| json!({ "name": name, "type": type_json(&schema, field) }))
+                .collect();
+            let accounts: Vec<Value> = instruction_accounts(name)
+                .iter()
+                .map(account_spec_json)
+                .collect();
+            json!({
+                "name": name,
+                "discriminant": discriminant,
+                "args": args,
+                "accounts": accounts,
+            })
+        })
+        .collect()
+}
+
+/// Fields of an account or type with their offsets, which stop at the first field of
+/// variable length
+fn struct_json(schema: &BorshSchemaContainer) -> Value {
+    let mut offset = Some(0);
+    let fields: Vec<Value> = named_fields(schema, &schema.declaration)
+        .iter()
+        .map(|(name, field)| {
+            let size = fixed_size(schema, field);
+            let json = json!({
+                "name": name,
+                "type": type_json(schema, field),
+                "offset": offset,
+                "size": size,
+            });
+            offset = offset.zip(size).map(|(offset, size)| offset + size);
+            json
+        })
+        .collect();
+    json!({
+        "name": schema.declaration,
+        "size": fixed_size(schema, &schema.declaration),
+        "fields": fields,
+    })
+}
+
+fn enum_json(schema: &BorshSchemaContainer) -> Value {
+    let variants: Vec<Value> = match definition(schema, &schema.declaration) {
+        Some(Definition::Enum { variants }) => variants
+            .iter()
+            .enumerate()
+            .map(|(discriminant, (name, _))| json!({ "name": name, "discriminant": discriminant }))
+            .collect(),
+        other => panic!("{} is not an enum: {:?}", schema.declaration, other),
+    };
+    json!({ "name": schema.declaration, "variants": variants })
+}
+
+fn idl() -> Value {
+    json!({
+        "name": "lunabank",
+        "version": env!("CARGO_PKG_VERSION"),
+        "instructions": instructions_json(),
+        "accounts": [
+            struct_json(&Lunabank::schema_container()),
+            struct_json(&LunaDeposit::schema_container()),
+            struct_json(&LunaRegistryEntry::schema_container()),
+            struct_json(&LunaAsset::schema_container()),
+        ],
+        "types": [
+            enum_json(&Key::schema_container()),
+            enum_json(&RecipeMode::schema_container()),
+        ],
+    })
+}
+
+#[test]
+fn test_idl_up_to_date() {
+    let idl = serde_json::to_string_pretty(&idl()).unwrap() + "\n";
+    if env::var_os("LUNABANK_UPDATE_IDL").is_some() {
+        fs::write(IDL_PATH, &idl).unwrap();
+        return;
+    }
+    let checked_in = fs::read_to_string(IDL_PATH).unwrap_or_default();
+    assert!(
+        checked_in == idl,
+        "{} is out of date, regenerate it with `LUNABANK_UPDATE_IDL=1 cargo test --test idl`",
+        IDL_PATH
+    );
+}
+
+#[test]
+fn test_sizes_match_state() {
+    let size = |schema: BorshSchemaContainer| fixed_size(&schema, &schema.declaration);
+    assert_eq!(size(Lunabank::schema_container()), Some(Lunabank::LEN));
+    assert_eq!(
+        size(LunaDeposit::schema_container()),
+        Some(LunaDeposit::LEN)
+    );
+    assert_eq!(
+        size(LunaRegistryEntry::schema_container()),
+        Some(LunaRegistryEntry::LEN)
+    );
+    assert_eq!(size(LunaAsset::schema_container()), Some(LunaAsset::LEN));
+}
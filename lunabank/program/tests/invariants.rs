@@ -0,0 +1,182 @@
+// Random deposit/withdraw/close sequences against the native program, checking after
+// every step that the vault covers what is owed and the recipe supply matches it
+mod common;
+
+use common::*;
+use lunabank::{
+    instruction,
+    state::{find_deposit_address, LunaDeposit, Lunabank},
+};
+use proptest::prelude::*;
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use solana_program_test::{tokio, ProgramTestContext};
+use solana_sdk::signature::Signer;
+
+const USERS: usize = 3;
+const USER_BALANCE: u64 = 10_000;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Deposit {
+        user: usize,
+        amount: u64,
+        deposit_time: u64,
+    },
+    Withdraw {
+        user: usize,
+    },
+    Warp {
+        seconds: u64,
+    },
+    Close,
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        4 => (0..USERS, 1..=USER_BALANCE / 4, 0..200u64).prop_map(
+            |(user, amount, deposit_time)| Op::Deposit {
+                user,
+                amount,
+                deposit_time,
+            }
+        ),
+        4 => (0..USERS).prop_map(|user| Op::Withdraw { user }),
+        2 => (0..200u64).prop_map(|seconds| Op::Warp { seconds }),
+        1 => Just(Op::Close),
+    ]
+}
+
+async fn account_data(context: &mut ProgramTestContext, address: &Pubkey) -> Option<Vec<u8>> {
+    context
+        .banks_client
+        .get_account(*address)
+        .await
+        .unwrap()
+        .map(|account| account.data)
+        .filter(|data| !data.is_empty())
+}
+
+async fn assert_invariants(context: &mut ProgramTestContext, bank: &Bank, users: &[User]) {
+    let mut locked = 0u64;
+    let mut deposits = 0u64;
+    for user in users {
+        let (deposit, _) =
+            find_deposit_address(&bank.program_id, &bank.config, &user.wallet.pubkey());
+        if let Some(data) = account_data(context, &deposit).await {
+            locked += LunaDeposit::unpack(&data).unwrap().amount;
+            deposits += 1;
+        }
+    }
+
+    let vault = token_account(context, &bank.vault).await.unwrap();
+    assert!(
+        vault.amount >= locked,
+        "vault holds {} for {} locked",
+        vault.amount,
+        locked
+    );
+
+    let recipe_mint =
+        spl_token::state::Mint::unpack(&account_data(context, &bank.recipe_mint).await.unwrap())
+            .unwrap();
+    assert_eq!(recipe_mint.supply, locked);
+
+    let bank_data = account_data(context, &bank.config).await;
+    match bank_data {
+        Some(data) => assert_eq!(Lunabank::unpack(&data).unwrap().active_deposits, deposits),
+        // a bank is only closed without deposits
+        None => assert_eq!(deposits, 0),
+    }
+
+    // no base tokens are created or lost
+    let balances = wallet_balances(context, users).await;
+    assert_eq!(balances + vault.amount, USER_BALANCE * users.len() as u64);
+}
+
+/// Base tokens left in the user wallets
+async fn wallet_balances(context: &mut ProgramTestContext, users: &[User]) -> u64 {
+    let mut sum = 0;
+    for user in users {
+        sum += token_account(context, &user.base).await.unwrap().amount;
+    }
+    sum
+}
+
+async fn run(ops: Vec<Op>) {
+    let (mut context, bank) = setup().await;
+    let mut users = Vec::with_capacity(USERS);
+    for _ in 0..USERS {
+        users.push(create_user(&mut context, &bank, USER_BALANCE).await);
+    }
+
+    for op in ops {
+        // failing steps (early withdraws, double deposits, closing with deposits, ...)
+        // are expected, they must leave the invariants intact as well
+        match op {
+            Op::Deposit {
+                user,
+                amount,
+                deposit_time,
+            } => {
+                // withdraw closes the recipe token account
+                if token_account(&mut context, &users[user].recipe)
+                    .await
+                    .is_none()
+                {
+                    let owner = users[user].wallet.pubkey();
+                    users[user].recipe =
+                        create_token_account(&mut context, &bank.recipe_mint, &owner).await;
+                }
+                let user = &users[user];
+                let ix = instruction::deposit(
+                    &bank.program_id,
+                    &bank.token_program,
+                    &user.wallet.pubkey(),
+                    &bank.config,
+                    &bank.base_mint.pubkey(),
+                    &user.base,
+                    &user.recipe,
+                    amount,
+                    deposit_time,
+                );
+                let _ = process(&mut context, &[ix], &[&user.wallet]).await;
+            }
+            Op::Withdraw { user } => {
+                let user = &users[user];
+                let ix = instruction::withdraw(
+                    &bank.program_id,
+                    &bank.token_program,
+                    &user.wallet.pubkey(),
+                    &bank.config,
+                    &bank.base_mint.pubkey(),
+                    &user.base,
+                    &user.recipe,
+                );
+                let _ = process(&mut context, &[ix], &[&user.wallet]).await;
+            }
+            Op::Warp { seconds } => warp_clock(&mut context, seconds).await,
+            Op::Close => {
+                let ix = instruction::close(
+                    &bank.program_id,
+                    &bank.token_program,
+                    &context.payer.pubkey(),
+                    &bank.config,
+                    &bank.base_mint.pubkey(),
+                    0,
+                );
+                let _ = process(&mut context, &[ix], &[]).await;
+            }
+        }
+        assert_invariants(&mut context, &bank, &users).await;
+    }
+}
+
+proptest! {
+    // every case boots a fresh bank
+    #![proptest_config(ProptestConfig::with_cases(16))]
+
+    #[test]
+    fn test_vault_solvency(ops in prop::collection::vec(op(), 1..24)) {
+        tokio::runtime::Runtime::new().unwrap().block_on(run(ops));
+    }
+}
@@ -0,0 +1,1524 @@
+// Runs the program natively inside solana-program-test, no validator needed
+mod common;
+
+use common::*;
+use lunabank::{
+    error::LunabankError,
+    instruction,
+    state::{
+        find_asset_address, find_bank_address, find_bank_authority_address, find_deposit_address,
+        find_recipe_mint_address, find_registry_address, find_unwrap_address, find_vault_address,
+        Key, LunaAsset, LunaDeposit, LunaRegistryEntry, Lunabank, RecipeMode,
+    },
+};
+use solana_program::{
+    instruction::Instruction, program_error::ProgramError, program_pack::Pack, pubkey::Pubkey,
+    system_instruction,
+};
+use solana_program_test::{tokio, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    signature::{Keypair, Signer},
+};
+use spl_token_2022::extension::ExtensionType;
+
+const DEPOSIT_TIME: u64 = 100;
+
+fn deposit_ix(bank: &Bank, user: &User, amount: u64) -> Instruction {
+    instruction::deposit(
+        &bank.program_id,
+        &bank.token_program,
+        &user.wallet.pubkey(),
+        &bank.config,
+        &bank.base_mint.pubkey(),
+        &user.base,
+        &user.recipe,
+        amount,
+        DEPOSIT_TIME,
+    )
+}
+
+fn withdraw_ix(bank: &Bank, user: &User) -> Instruction {
+    instruction::withdraw(
+        &bank.program_id,
+        &bank.token_program,
+        &user.wallet.pubkey(),
+        &bank.config,
+        &bank.base_mint.pubkey(),
+        &user.base,
+        &user.recipe,
+    )
+}
+
+fn close_ix(bank: &Bank, owner: &Pubkey) -> Instruction {
+    instruction::close(
+        &bank.program_id,
+        &bank.token_program,
+        owner,
+        &bank.config,
+        &bank.base_mint.pubkey(),
+        0,
+    )
+}
+
+/// Rewrites a config or deposit account the way the V1 program left it, in the V1 layout
+/// and rent exempt for its size. The surplus goes to the payer, the bank checks the total
+/// supply when warping.
+async fn downgrade_to_v1(context: &mut ProgramTestContext, address: &Pubkey) {
+    let account = get_account(context, address).await;
+    let mut data;
+    if account.data[0] == Key::LunabankV2 as u8 {
+        let bank = Lunabank::unpack(&account.data).unwrap();
+        data = vec![Key::LunabankV1 as u8];
+        data.extend_from_slice(bank.owner.as_ref());
+        data.extend_from_slice(bank.base_token_mint.as_ref());
+        data.extend_from_slice(bank.recipe_token_mint.as_ref());
+        data.extend_from_slice(bank.base_token_account.as_ref());
+        data.extend_from_slice(&bank.coef.to_le_bytes());
+    } else {
+        let deposit = LunaDeposit::unpack(&account.data).unwrap();
+        data = vec![Key::LunaDepositV1 as u8];
+        data.extend_from_slice(deposit.owner.as_ref());
+        data.extend_from_slice(&deposit.amount.to_le_bytes());
+        data.extend_from_slice(&deposit.start_timestamp.to_le_bytes());
+        data.extend_from_slice(&deposit.end_timestamp.to_le_bytes());
+    }
+    shrink_to(context, address, data).await;
+}
+
+/// Rewrites a deposit account the way the program left it before deposits recorded their
+/// bank, like `downgrade_to_v1`
+async fn downgrade_deposit_to_v2(context: &mut ProgramTestContext, address: &Pubkey) {
+    let account = get_account(context, address).await;
+    let mut data = account.data[..LunaDeposit::BANK_OFFSET].to_vec();
+    data[LunaDeposit::KEY_OFFSET] = Key::LunaDepositV2 as u8;
+    data.extend_from_slice(&[0; 32]);
+    shrink_to(context, address, data).await;
+}
+
+/// Replaces the data of `address` with the shorter `data`, keeping it rent exempt
+async fn shrink_to(context: &mut ProgramTestContext, address: &Pubkey, data: Vec<u8>) {
+    let mut account = get_account(context, address).await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let surplus = account.lamports - rent.minimum_balance(data.len());
+    account.lamports -= surplus;
+    account.data = data;
+    context.set_account(address, &account.into());
+
+    let payer = context.payer.pubkey();
+    let mut payer_account = get_account(context, &payer).await;
+    payer_account.lamports += surplus;
+    context.set_account(&payer, &payer_account.into());
+}
+
+async fn get_account(context: &mut ProgramTestContext, address: &Pubkey) -> Account {
+    context
+        .banks_client
+        .get_account(*address)
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_lifecycle() {
+    let (mut context, bank) = setup().await;
+    let user = create_user(&mut context, &bank, 1_000).await;
+
+    process(
+        &mut context,
+        &[deposit_ix(&bank, &user, 100)],
+        &[&user.wallet],
+    )
+    .await
+    .unwrap();
+
+    let user_base = token_account(&mut context, &user.base).await.unwrap();
+    assert_eq!(user_base.amount, 900);
+    let vault = token_account(&mut context, &bank.vault).await.unwrap();
+    assert_eq!(vault.amount, 100);
+    let user_recipe = token_account(&mut context, &user.recipe).await.unwrap();
+    assert_eq!(user_recipe.amount, 100);
+    assert!(user_recipe.is_frozen());
+    assert_eq!(bank_state(&mut context, &bank).await.active_deposits, 1);
+
+    let (deposit, _) = find_deposit_address(&bank.program_id, &bank.config, &user.wallet.pubkey());
+    let deposit_account = context
+        .banks_client
+        .get_account(deposit)
+        .await
+        .unwrap()
+        .unwrap();
+    let deposit_data = LunaDeposit::unpack(&deposit_account.data).unwrap();
+    assert_eq!(deposit_data.owner, user.wallet.pubkey());
+    assert_eq!(deposit_data.amount, 100);
+    assert_eq!(deposit_data.bank, bank.config);
+    assert_eq!(deposit_data.base_token_mint, bank.base_mint.pubkey());
+    assert_eq!(
+        deposit_data.end_timestamp - deposit_data.start_timestamp,
+        DEPOSIT_TIME
+    );
+
+    // the deposit is still locked
+    assert_error(
+        process(&mut context, &[withdraw_ix(&bank, &user)], &[&user.wallet]).await,
+        LunabankError::WaitPeriodBreach,
+    );
+
+    warp_clock(&mut context, DEPOSIT_TIME).await;
+    process(&mut context, &[withdraw_ix(&bank, &user)], &[&user.wallet])
+        .await
+        .unwrap();
+
+    let user_base = token_account(&mut context, &user.base).await.unwrap();
+    assert_eq!(user_base.amount, 1_000);
+    let vault = token_account(&mut context, &bank.vault).await.unwrap();
+    assert_eq!(vault.amount, 0);
+    assert!(token_account(&mut context, &user.recipe).await.is_none());
+    assert!(context
+        .banks_client
+        .get_account(deposit)
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(bank_state(&mut context, &bank).await.active_deposits, 0);
+
+    let owner = context.payer.pubkey();
+    process(&mut context, &[close_ix(&bank, &owner)], &[])
+        .await
+        .unwrap();
+    assert!(context
+        .banks_client
+        .get_account(bank.config)
+        .await
+        .unwrap()
+        .is_none());
+    let vault = token_account(&mut context, &bank.vault).await.unwrap();
+    assert_eq!(vault.owner, owner);
+}
+
+#[tokio::test]
+async fn test_init_authority_failures() {
+    let (mut context, bank) = setup().await;
+    let base_mint = bank.base_mint.pubkey();
+    let initializer = Keypair::new();
+
+    // initializer does not sign
+    let mut ix = instruction::init(
+        &bank.program_id,
+        &bank.token_program,
+        &initializer.pubkey(),
+        &base_mint,
+        1,
+    );
+    ix.accounts[0].is_signer = false;
+    assert_error(
+        process(&mut context, &[ix], &[]).await,
+        ProgramError::MissingRequiredSignature,
+    );
+
+    let payer = context.payer.pubkey();
+
+    // config of another bank id
+    let mut ix = instruction::init(&bank.program_id, &bank.token_program, &payer, &base_mint, 1);
+    ix.accounts[1].pubkey = find_bank_address(&bank.program_id, &base_mint, 2).0;
+    assert_error(
+        process(&mut context, &[ix], &[]).await,
+        LunabankError::DerivedKeyInvalid,
+    );
+
+    // bank authority of another bank
+    let mut ix = instruction::init(&bank.program_id, &bank.token_program, &payer, &base_mint, 1);
+    ix.accounts[9].pubkey =
+        instruction::init(&bank.program_id, &bank.token_program, &payer, &base_mint, 2).accounts[9]
+            .pubkey;
+    assert_error(
+        process(&mut context, &[ix], &[]).await,
+        LunabankError::DerivedKeyInvalid,
+    );
+}
+
+#[tokio::test]
+async fn test_deposit_authority_failures() {
+    let (mut context, bank) = setup().await;
+    let user = create_user(&mut context, &bank, 1_000).await;
+    let other = create_user(&mut context, &bank, 1_000).await;
+
+    // bank authority not derived from the config
+    let mut ix = deposit_ix(&bank, &user, 100);
+    ix.accounts[8].pubkey = context.payer.pubkey();
+    assert_error(
+        process(&mut context, &[ix], &[&user.wallet]).await,
+        LunabankError::DerivedKeyInvalid,
+    );
+
+    // deposit account of another user
+    let mut ix = deposit_ix(&bank, &user, 100);
+    ix.accounts[7].pubkey = deposit_ix(&bank, &other, 100).accounts[7].pubkey;
+    assert_error(
+        process(&mut context, &[ix], &[&user.wallet]).await,
+        LunabankError::DerivedKeyInvalid,
+    );
+
+    // base tokens of another user
+    let mut ix = deposit_ix(&bank, &user, 100);
+    ix.accounts[4].pubkey = other.base;
+    assert_error(
+        process(&mut context, &[ix], &[&user.wallet]).await,
+        LunabankError::TokenOwnerMismatch,
+    );
+
+    // base mint of another bank
+    let other_mint = create_mint(&mut context).await;
+    let mut ix = deposit_ix(&bank, &user, 100);
+    ix.accounts[2].pubkey = other_mint.pubkey();
+    assert_error(
+        process(&mut context, &[ix], &[&user.wallet]).await,
+        LunabankError::BaseMintMismatch,
+    );
+
+    // recipe mint of another bank
+    let mut ix = deposit_ix(&bank, &user, 100);
+    ix.accounts[5].pubkey = other_mint.pubkey();
+    assert_error(
+        process(&mut context, &[ix], &[&user.wallet]).await,
+        LunabankError::RecipeMintMismatch,
+    );
+
+    // base tokens sent to an account of the base mint that isn't the vault
+    let mut ix = deposit_ix(&bank, &user, 100);
+    ix.accounts[3].pubkey = other.base;
+    assert_error(
+        process(&mut context, &[ix], &[&user.wallet]).await,
+        LunabankError::VaultMismatch,
+    );
+
+    // base tokens someone else may move
+    let delegated = create_user(&mut context, &bank, 1_000).await;
+    let ix = spl_token::instruction::approve(
+        &spl_token::id(),
+        &delegated.base,
+        &other.wallet.pubkey(),
+        &delegated.wallet.pubkey(),
+        &[],
+        1,
+    )
+    .unwrap();
+    process(&mut context, &[ix], &[&delegated.wallet])
+        .await
+        .unwrap();
+    assert_error(
+        process(
+            &mut context,
+            &[deposit_ix(&bank, &delegated, 100)],
+            &[&delegated.wallet],
+        )
+        .await,
+        LunabankError::TokenDelegateNotAllowed,
+    );
+
+    // base tokens someone else may close the account of
+    let closable = create_user(&mut context, &bank, 1_000).await;
+    let ix = spl_token::instruction::set_authority(
+        &spl_token::id(),
+        &closable.base,
+        Some(&other.wallet.pubkey()),
+        spl_token::instruction::AuthorityType::CloseAccount,
+        &closable.wallet.pubkey(),
+        &[],
+    )
+    .unwrap();
+    process(&mut context, &[ix], &[&closable.wallet])
+        .await
+        .unwrap();
+    assert_error(
+        process(
+            &mut context,
+            &[deposit_ix(&bank, &closable, 100)],
+            &[&closable.wallet],
+        )
+        .await,
+        LunabankError::TokenCloseAuthorityNotAllowed,
+    );
+
+    // recipe mint the bank authority no longer mints, last as it leaves the bank unusable
+    let mut tampered = get_account(&mut context, &bank.recipe_mint).await;
+    let mut recipe_mint = spl_token::state::Mint::unpack(&tampered.data).unwrap();
+    recipe_mint.mint_authority = Some(Pubkey::new_unique()).into();
+    spl_token::state::Mint::pack(recipe_mint, &mut tampered.data).unwrap();
+    context.set_account(&bank.recipe_mint, &tampered.into());
+    assert_error(
+        process(
+            &mut context,
+            &[deposit_ix(&bank, &user, 100)],
+            &[&user.wallet],
+        )
+        .await,
+        LunabankError::BankAuthorityMismatch,
+    );
+}
+
+#[tokio::test]
+async fn test_withdraw_authority_failures() {
+    let (mut context, bank) = setup().await;
+    let user = create_user(&mut context, &bank, 1_000).await;
+    let other = create_user(&mut context, &bank, 1_000).await;
+    process(
+        &mut context,
+        &[deposit_ix(&bank, &user, 100)],
+        &[&user.wallet],
+    )
+    .await
+    .unwrap();
+    warp_clock(&mut context, DEPOSIT_TIME).await;
+
+    // someone else claims the deposit
+    let mut ix = withdraw_ix(&bank, &other);
+    ix.accounts[7].pubkey = withdraw_ix(&bank, &user).accounts[7].pubkey;
+    assert_error(
+        process(&mut context, &[ix], &[&other.wallet]).await,
+        LunabankError::DerivedKeyInvalid,
+    );
+
+    // bank authority not derived from the config
+    let mut ix = withdraw_ix(&bank, &user);
+    ix.accounts[8].pubkey = context.payer.pubkey();
+    assert_error(
+        process(&mut context, &[ix], &[&user.wallet]).await,
+        LunabankError::DerivedKeyInvalid,
+    );
+
+    // recipe tokens of another user
+    let mut ix = withdraw_ix(&bank, &user);
+    ix.accounts[6].pubkey = other.recipe;
+    assert_error(
+        process(&mut context, &[ix], &[&user.wallet]).await,
+        LunabankError::TokenOwnerMismatch,
+    );
+
+    // deposit recorded in another bank, on a deposit of its own so the one of `user` is
+    // never rewritten under the program
+    process(
+        &mut context,
+        &[deposit_ix(&bank, &other, 100)],
+        &[&other.wallet],
+    )
+    .await
+    .unwrap();
+    warp_clock(&mut context, DEPOSIT_TIME).await;
+    let (deposit, _) = find_deposit_address(&bank.program_id, &bank.config, &other.wallet.pubkey());
+    let mut tampered = get_account(&mut context, &deposit).await;
+    tampered.data[LunaDeposit::BANK_OFFSET..LunaDeposit::BANK_OFFSET + 32]
+        .copy_from_slice(Pubkey::new_unique().as_ref());
+    context.set_account(&deposit, &tampered.into());
+    assert_error(
+        process(
+            &mut context,
+            &[withdraw_ix(&bank, &other)],
+            &[&other.wallet],
+        )
+        .await,
+        LunabankError::DepositBankMismatch,
+    );
+
+    // deposit recorded for someone else than the wallet it is derived from
+    let third = create_user(&mut context, &bank, 1_000).await;
+    process(
+        &mut context,
+        &[deposit_ix(&bank, &third, 100)],
+        &[&third.wallet],
+    )
+    .await
+    .unwrap();
+    warp_clock(&mut context, DEPOSIT_TIME).await;
+    let (deposit, _) = find_deposit_address(&bank.program_id, &bank.config, &third.wallet.pubkey());
+    let mut tampered = get_account(&mut context, &deposit).await;
+    tampered.data[LunaDeposit::OWNER_OFFSET..LunaDeposit::OWNER_OFFSET + 32]
+        .copy_from_slice(user.wallet.pubkey().as_ref());
+    context.set_account(&deposit, &tampered.into());
+    assert_error(
+        process(
+            &mut context,
+            &[withdraw_ix(&bank, &third)],
+            &[&third.wallet],
+        )
+        .await,
+        LunabankError::DepositOwnerMismatch,
+    );
+
+    process(&mut context, &[withdraw_ix(&bank, &user)], &[&user.wallet])
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_registry() {
+    let (mut context, bank) = setup().await;
+    let owner = context.payer.pubkey();
+    let mint = bank.base_mint.pubkey();
+    let ix = instruction::init(&bank.program_id, &bank.token_program, &owner, &mint, 1);
+    process(&mut context, &[ix], &[]).await.unwrap();
+    let (other_config, _) = find_bank_address(&bank.program_id, &mint, 1);
+
+    let entries = [
+        find_registry_address(&bank.program_id, &mint, 0).0,
+        find_registry_address(&bank.program_id, &mint, 1).0,
+    ];
+    for (bank_id, (entry, config)) in entries.iter().zip([bank.config, other_config]).enumerate() {
+        let account = get_account(&mut context, entry).await;
+        assert_eq!(account.owner, bank.program_id);
+        assert_eq!(
+            LunaRegistryEntry::unpack(&account.data).unwrap(),
+            LunaRegistryEntry {
+                key: Key::LunaRegistryEntryV1,
+                bank: config,
+                base_token_mint: mint,
+                bank_id: bank_id as u64,
+            }
+        );
+    }
+
+    // the entry has to be the one of the bank id in the config
+    let mut ix = close_ix(&bank, &owner);
+    ix.accounts[6].pubkey = entries[1];
+    process(&mut context, &[ix], &[]).await.unwrap_err();
+
+    // closing a bank closes its entry only
+    process(&mut context, &[close_ix(&bank, &owner)], &[])
+        .await
+        .unwrap();
+    assert!(context
+        .banks_client
+        .get_account(entries[0])
+        .await
+        .unwrap()
+        .is_none());
+    get_account(&mut context, &entries[1]).await;
+
+    // the entry address of a bank from before the registry may belong to another bank
+    let mut tampered = get_account(&mut context, &entries[1]).await;
+    tampered.data[LunaRegistryEntry::BANK_OFFSET..LunaRegistryEntry::BANK_OFFSET + 32]
+        .copy_from_slice(Pubkey::new_unique().as_ref());
+    context.set_account(&entries[1], &tampered.into());
+    let ix = instruction::close(
+        &bank.program_id,
+        &bank.token_program,
+        &owner,
+        &other_config,
+        &mint,
+        1,
+    );
+    process(&mut context, &[ix], &[]).await.unwrap();
+    assert!(context
+        .banks_client
+        .get_account(other_config)
+        .await
+        .unwrap()
+        .is_none());
+    get_account(&mut context, &entries[1]).await;
+}
+
+#[tokio::test]
+async fn test_close_authority_failures() {
+    let (mut context, bank) = setup().await;
+    let user = create_user(&mut context, &bank, 1_000).await;
+
+    // only the bank owner may close it
+    assert_error(
+        process(
+            &mut context,
+            &[close_ix(&bank, &user.wallet.pubkey())],
+            &[&user.wallet],
+        )
+        .await,
+        LunabankError::BankOwnerMismatch,
+    );
+
+    process(
+        &mut context,
+        &[deposit_ix(&bank, &user, 100)],
+        &[&user.wallet],
+    )
+    .await
+    .unwrap();
+    let owner = context.payer.pubkey();
+    assert_error(
+        process(&mut context, &[close_ix(&bank, &owner)], &[]).await,
+        LunabankError::DepositsOutstanding,
+    );
+}
+
+#[tokio::test]
+async fn test_shutdown() {
+    let (mut context, bank) = setup().await;
+    let user = create_user(&mut context, &bank, 1_000).await;
+    process(
+        &mut context,
+        &[deposit_ix(&bank, &user, 100)],
+        &[&user.wallet],
+    )
+    .await
+    .unwrap();
+
+    // only the bank owner may shut it down
+    let ix = instruction::shutdown(&bank.program_id, &user.wallet.pubkey(), &bank.config);
+    assert_error(
+        process(&mut context, &[ix], &[&user.wallet]).await,
+        LunabankError::BankOwnerMismatch,
+    );
+
+    let ix = instruction::shutdown(&bank.program_id, &context.payer.pubkey(), &bank.config);
+    process(&mut context, &[ix], &[]).await.unwrap();
+    assert!(bank_state(&mut context, &bank).await.is_shutdown);
+
+    let other = create_user(&mut context, &bank, 1_000).await;
+    assert_error(
+        process(
+            &mut context,
+            &[deposit_ix(&bank, &other, 100)],
+            &[&other.wallet],
+        )
+        .await,
+        LunabankError::BankShutdown,
+    );
+
+    // existing deposits are released without waiting
+    process(&mut context, &[withdraw_ix(&bank, &user)], &[&user.wallet])
+        .await
+        .unwrap();
+    let user_base = token_account(&mut context, &user.base).await.unwrap();
+    assert_eq!(user_base.amount, 1_000);
+}
+
+#[tokio::test]
+async fn test_deposit_logs() {
+    let (mut context, bank) = setup().await;
+    let user = create_user(&mut context, &bank, 1_000).await;
+
+    let (result, logs) = process_with_logs(
+        &mut context,
+        &[deposit_ix(&bank, &user, 100)],
+        &[&user.wallet],
+    )
+    .await;
+    result.unwrap();
+    let own_logs = program_logs(&bank.program_id, &logs);
+
+    // every log is a syscall charged at least 100 compute units, diagnostics are only
+    // written with the `verbose-logs` feature
+    if cfg!(feature = "verbose-logs") {
+        assert!(own_logs.len() > 1, "{:?}", own_logs);
+    } else {
+        assert_eq!(
+            own_logs,
+            vec![format!(
+                "Program log: Instruction: DepositInstruction 100 {}",
+                DEPOSIT_TIME
+            )]
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_migrate() {
+    let (mut context, bank) = setup().await;
+    let user = create_user(&mut context, &bank, 1_000).await;
+    process(
+        &mut context,
+        &[deposit_ix(&bank, &user, 100)],
+        &[&user.wallet],
+    )
+    .await
+    .unwrap();
+    let (deposit, deposit_bump) =
+        find_deposit_address(&bank.program_id, &bank.config, &user.wallet.pubkey());
+    downgrade_to_v1(&mut context, &bank.config).await;
+    downgrade_to_v1(&mut context, &deposit).await;
+    assert_eq!(
+        get_account(&mut context, &bank.config).await.data.len(),
+        Lunabank::LEN_V1
+    );
+    assert_eq!(
+        get_account(&mut context, &deposit).await.data.len(),
+        LunaDeposit::LEN_V1
+    );
+
+    // V1 accounts lack the counters and bump seeds, they are migrated before use
+    assert_error(
+        process(&mut context, &[withdraw_ix(&bank, &user)], &[&user.wallet]).await,
+        LunabankError::AccountNotMigrated,
+    );
+
+    let payer = context.payer.pubkey();
+    // a bank is migrated on its own
+    let ix = instruction::migrate(&bank.program_id, &payer, &bank.config, &deposit);
+    assert_error(
+        process(&mut context, &[ix], &[]).await,
+        ProgramError::InvalidAccountData,
+    );
+    // and before its deposits
+    let ix = instruction::migrate(&bank.program_id, &payer, &deposit, &bank.config);
+    assert_error(
+        process(&mut context, &[ix], &[]).await,
+        LunabankError::AccountNotMigrated,
+    );
+
+    // the deposit is migrated below by the same transaction, on a new slot for a fresh
+    // blockhash
+    warp_clock(&mut context, 0).await;
+
+    let rent = context.banks_client.get_rent().await.unwrap();
+    for (address, len) in [(bank.config, Lunabank::LEN), (deposit, LunaDeposit::LEN)] {
+        let ix = instruction::migrate(&bank.program_id, &payer, &address, &bank.config);
+        process(&mut context, &[ix], &[]).await.unwrap();
+        let account = get_account(&mut context, &address).await;
+        assert_eq!(account.data.len(), len);
+        assert_eq!(account.lamports, rent.minimum_balance(len));
+
+        // the migrated bank doesn't count the deposit still in the V1 layout yet
+        if address == bank.config {
+            assert_eq!(bank_state(&mut context, &bank).await.active_deposits, 0);
+            assert_error(
+                process(&mut context, &[close_ix(&bank, &payer)], &[]).await,
+                LunabankError::DepositsOutstanding,
+            );
+        }
+    }
+    let bank_data = bank_state(&mut context, &bank).await;
+    assert_eq!(bank_data.key, Key::LunabankV2);
+    assert_eq!(
+        bank_data.pda_bump_seed,
+        find_bank_authority_address(&bank.program_id, &bank.config).1
+    );
+    assert_eq!(bank_data.token_program, spl_token::id());
+    // counted in by the migration of the deposit
+    assert_eq!(bank_data.active_deposits, 1);
+    let deposit_data =
+        LunaDeposit::unpack(&get_account(&mut context, &deposit).await.data).unwrap();
+    assert_eq!(deposit_data.key, Key::LunaDepositV3);
+    assert_eq!(deposit_data.bump_seed, deposit_bump);
+    assert_eq!(deposit_data.bank, bank.config);
+    assert_eq!(deposit_data.base_token_mint, bank.base_mint.pubkey());
+    assert_eq!(deposit_data.amount, 100);
+
+    // migrating twice is a no-op, on a new slot to get a fresh blockhash
+    warp_clock(&mut context, DEPOSIT_TIME).await;
+    for address in [bank.config, deposit] {
+        let ix = instruction::migrate(&bank.program_id, &payer, &address, &bank.config);
+        process(&mut context, &[ix], &[]).await.unwrap();
+    }
+    assert_eq!(bank_state(&mut context, &bank).await.active_deposits, 1);
+
+    // token accounts are no lunabank state
+    let ix = instruction::migrate(&bank.program_id, &payer, &bank.vault, &bank.config);
+    assert_error(
+        process(&mut context, &[ix], &[]).await,
+        ProgramError::IllegalOwner,
+    );
+
+    process(&mut context, &[withdraw_ix(&bank, &user)], &[&user.wallet])
+        .await
+        .unwrap();
+    let user_base = token_account(&mut context, &user.base).await.unwrap();
+    assert_eq!(user_base.amount, 1_000);
+    assert_eq!(bank_state(&mut context, &bank).await.active_deposits, 0);
+}
+
+#[tokio::test]
+async fn test_migrate_v2_deposit() {
+    let (mut context, bank) = setup().await;
+    let user = create_user(&mut context, &bank, 1_000).await;
+    process(
+        &mut context,
+        &[deposit_ix(&bank, &user, 100)],
+        &[&user.wallet],
+    )
+    .await
+    .unwrap();
+    let (deposit, deposit_bump) =
+        find_deposit_address(&bank.program_id, &bank.config, &user.wallet.pubkey());
+    downgrade_deposit_to_v2(&mut context, &deposit).await;
+    assert_eq!(
+        get_account(&mut context, &deposit).await.data.len(),
+        LunaDeposit::LEN_V2
+    );
+
+    warp_clock(&mut context, DEPOSIT_TIME).await;
+    assert_error(
+        process(&mut context, &[withdraw_ix(&bank, &user)], &[&user.wallet]).await,
+        LunabankError::AccountNotMigrated,
+    );
+
+    // the stored bump seed only derives the deposit from its own bank
+    let payer = context.payer.pubkey();
+    let base_mint = bank.base_mint.pubkey();
+    let ix = instruction::init(&bank.program_id, &bank.token_program, &payer, &base_mint, 1);
+    process(&mut context, &[ix], &[]).await.unwrap();
+    let (other_config, _) = find_bank_address(&bank.program_id, &base_mint, 1);
+    let ix = instruction::migrate(&bank.program_id, &payer, &deposit, &other_config);
+    assert_error(
+        process(&mut context, &[ix], &[]).await,
+        LunabankError::DerivedKeyInvalid,
+    );
+
+    let ix = instruction::migrate(&bank.program_id, &payer, &deposit, &bank.config);
+    process(&mut context, &[ix], &[]).await.unwrap();
+    let account = get_account(&mut context, &deposit).await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+    assert_eq!(account.data.len(), LunaDeposit::LEN);
+    assert_eq!(account.lamports, rent.minimum_balance(LunaDeposit::LEN));
+    let deposit_data = LunaDeposit::unpack(&account.data).unwrap();
+    assert_eq!(deposit_data.key, Key::LunaDepositV3);
+    assert_eq!(deposit_data.bump_seed, deposit_bump);
+    assert_eq!(deposit_data.bank, bank.config);
+    assert_eq!(deposit_data.base_token_mint, bank.base_mint.pubkey());
+    assert_eq!(deposit_data.amount, 100);
+    // V2 banks counted the deposit when it was made
+    assert_eq!(bank_state(&mut context, &bank).await.active_deposits, 1);
+
+    process(&mut context, &[withdraw_ix(&bank, &user)], &[&user.wallet])
+        .await
+        .unwrap();
+    assert_eq!(bank_state(&mut context, &bank).await.active_deposits, 0);
+}
+
+#[tokio::test]
+async fn test_sol_bank() {
+    let (mut context, program_id) = start().await;
+    let native_mint = spl_token::native_mint::id();
+    let (config, _) = find_bank_address(&program_id, &native_mint, 0);
+    let vault = find_vault_address(&program_id, &config).0;
+    let recipe_mint = find_recipe_mint_address(&program_id, &config).0;
+    let ix = instruction::init(
+        &program_id,
+        &spl_token::id(),
+        &context.payer.pubkey(),
+        &native_mint,
+        0,
+    );
+    process(&mut context, &[ix], &[]).await.unwrap();
+
+    let wallet = create_wallet(&mut context).await;
+    let recipe = create_token_account(&mut context, &recipe_mint, &wallet.pubkey()).await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let lamports = get_account(&mut context, &wallet.pubkey()).await.lamports;
+
+    // lamports are only taken from the payer
+    let ix = instruction::deposit(
+        &program_id,
+        &spl_token::id(),
+        &wallet.pubkey(),
+        &config,
+        &native_mint,
+        &Pubkey::new_unique(),
+        &recipe,
+        100,
+        DEPOSIT_TIME,
+    );
+    assert_error(
+        process(&mut context, &[ix], &[&wallet]).await,
+        ProgramError::InvalidAccountData,
+    );
+
+    let amount = 100_000_000;
+    let ix = instruction::deposit_sol(&program_id, &wallet.pubkey(), &config, &recipe, amount, 0);
+    process(&mut context, &[ix], &[&wallet]).await.unwrap();
+    assert_eq!(
+        get_account(&mut context, &wallet.pubkey()).await.lamports,
+        lamports - amount - rent.minimum_balance(LunaDeposit::LEN)
+    );
+    assert_eq!(
+        token_account(&mut context, &vault).await.unwrap().amount,
+        amount
+    );
+    assert_eq!(
+        token_account(&mut context, &recipe).await.unwrap().amount,
+        amount
+    );
+
+    // lamports are only paid out through the unwrap account
+    let ix = instruction::withdraw(
+        &program_id,
+        &spl_token::id(),
+        &wallet.pubkey(),
+        &config,
+        &native_mint,
+        &wallet.pubkey(),
+        &recipe,
+    );
+    assert_error(
+        process(&mut context, &[ix], &[&wallet]).await,
+        LunabankError::DerivedKeyInvalid,
+    );
+
+    // funding the unwrap address beforehand does not block the withdrawal, the
+    // lamports end up with the depositor
+    let (unwrap, _) = find_unwrap_address(&program_id, &config, &wallet.pubkey());
+    let prefunded = rent.minimum_balance(0);
+    let ix = system_instruction::transfer(&context.payer.pubkey(), &unwrap, prefunded);
+    process(&mut context, &[ix], &[]).await.unwrap();
+
+    let ix = instruction::withdraw_sol(&program_id, &wallet.pubkey(), &config, &recipe);
+    process(&mut context, &[ix], &[&wallet]).await.unwrap();
+    // the amount, the deposit rent and the rent of the closed recipe account
+    assert_eq!(
+        get_account(&mut context, &wallet.pubkey()).await.lamports,
+        lamports + prefunded + rent.minimum_balance(spl_token::state::Account::LEN)
+    );
+    assert_eq!(token_account(&mut context, &vault).await.unwrap().amount, 0);
+    assert!(context
+        .banks_client
+        .get_account(unwrap)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_token_2022_bank() {
+    let (mut context, bank) = setup_with(&spl_token_2022::id(), RecipeMode::NonTransferable).await;
+    let user = create_user(&mut context, &bank, 1_000).await;
+    let state = bank_state(&mut context, &bank).await;
+    assert_eq!(state.recipe_mode, RecipeMode::NonTransferable);
+    assert_eq!(state.token_program, spl_token_2022::id());
+
+    process(
+        &mut context,
+        &[deposit_ix(&bank, &user, 100)],
+        &[&user.wallet],
+    )
+    .await
+    .unwrap();
+    let user_recipe = token_account(&mut context, &user.recipe).await.unwrap();
+    assert_eq!(user_recipe.amount, 100);
+    assert!(!user_recipe.is_frozen());
+
+    // receipts can't leave the depositor
+    let other = create_token_account(&mut context, &bank.recipe_mint, &bank.config).await;
+    let ix = spl_token_2022::instruction::transfer_checked(
+        &bank.token_program,
+        &user.recipe,
+        &bank.recipe_mint,
+        &other,
+        &user.wallet.pubkey(),
+        &[],
+        100,
+        6,
+    )
+    .unwrap();
+    assert!(process(&mut context, &[ix], &[&user.wallet]).await.is_err());
+
+    // the bank runs on the program of its mint
+    let mut ix = deposit_ix(&bank, &user, 100);
+    for account in ix.accounts.iter_mut() {
+        if account.pubkey == bank.token_program {
+            account.pubkey = spl_token::id();
+        }
+    }
+    assert_error(
+        process(&mut context, &[ix], &[&user.wallet]).await,
+        LunabankError::TokenProgramMismatch,
+    );
+
+    warp_clock(&mut context, DEPOSIT_TIME).await;
+    process(&mut context, &[withdraw_ix(&bank, &user)], &[&user.wallet])
+        .await
+        .unwrap();
+    let user_base = token_account(&mut context, &user.base).await.unwrap();
+    assert_eq!(user_base.amount, 1_000);
+    let vault = token_account(&mut context, &bank.vault).await.unwrap();
+    assert_eq!(vault.amount, 0);
+    assert!(token_account(&mut context, &user.recipe).await.is_none());
+}
+
+#[tokio::test]
+async fn test_token_2022_init_failures() {
+    let (mut context, program_id) = start().await;
+    let payer = context.payer.pubkey();
+
+    // a permanent delegate could move tokens out of the vault
+    let mint = Keypair::new();
+    let extension = spl_token_2022::instruction::initialize_permanent_delegate(
+        &spl_token_2022::id(),
+        &mint.pubkey(),
+        &payer,
+    )
+    .unwrap();
+    let mint = create_mint_with(
+        &mut context,
+        mint,
+        &spl_token_2022::id(),
+        &[ExtensionType::PermanentDelegate],
+        vec![extension],
+    )
+    .await;
+    let ix = instruction::init(
+        &program_id,
+        &spl_token_2022::id(),
+        &payer,
+        &mint.pubkey(),
+        0,
+    );
+    assert_error(
+        process(&mut context, &[ix], &[]).await,
+        LunabankError::UnsupportedMintExtension,
+    );
+
+    // spl-token has no non-transferable mints
+    let mint = create_mint(&mut context).await;
+    let ix = instruction::init_with_options(
+        &program_id,
+        &spl_token::id(),
+        &payer,
+        &mint.pubkey(),
+        0,
+        RecipeMode::NonTransferable,
+    );
+    assert_error(
+        process(&mut context, &[ix], &[]).await,
+        LunabankError::RecipeModeUnsupported,
+    );
+}
+
+#[tokio::test]
+async fn test_transferable_bank() {
+    let (mut context, bank) = setup_with(&spl_token::id(), RecipeMode::Transferable).await;
+    let user = create_user(&mut context, &bank, 1_000).await;
+    let other = create_user(&mut context, &bank, 1_000).await;
+    let recipe_mint = context
+        .banks_client
+        .get_account(bank.recipe_mint)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(spl_token::state::Mint::unpack(&recipe_mint.data)
+        .unwrap()
+        .freeze_authority
+        .is_none());
+
+    process(
+        &mut context,
+        &[deposit_ix(&bank, &user, 100)],
+        &[&user.wallet],
+    )
+    .await
+    .unwrap();
+    let user_recipe = token_account(&mut context, &user.recipe).await.unwrap();
+    assert_eq!(user_recipe.amount, 100);
+    assert!(!user_recipe.is_frozen());
+
+    // the receipts change hands
+    let ix = spl_token::instruction::transfer(
+        &spl_token::id(),
+        &user.recipe,
+        &other.recipe,
+        &user.wallet.pubkey(),
+        &[],
+        100,
+    )
+    .unwrap();
+    process(&mut context, &[ix], &[&user.wallet]).await.unwrap();
+    warp_clock(&mut context, DEPOSIT_TIME).await;
+
+    // not enough receipts left in the account of the depositor
+    assert!(
+        process(&mut context, &[withdraw_ix(&bank, &user)], &[&user.wallet])
+            .await
+            .is_err()
+    );
+
+    // receipts of an account the depositor does not control
+    let mut ix = withdraw_ix(&bank, &user);
+    ix.accounts[6].pubkey = other.recipe;
+    assert!(process(&mut context, &[ix.clone()], &[&user.wallet])
+        .await
+        .is_err());
+
+    // base tokens are not receipts
+    let mut wrong_mint = withdraw_ix(&bank, &user);
+    wrong_mint.accounts[6].pubkey = other.base;
+    assert_error(
+        process(&mut context, &[wrong_mint], &[&user.wallet]).await,
+        LunabankError::TokenMintMismatch,
+    );
+
+    // once delegated to the depositor they can be burnt
+    let approve = spl_token::instruction::approve(
+        &spl_token::id(),
+        &other.recipe,
+        &user.wallet.pubkey(),
+        &other.wallet.pubkey(),
+        &[],
+        100,
+    )
+    .unwrap();
+    process(&mut context, &[approve], &[&other.wallet])
+        .await
+        .unwrap();
+    process(&mut context, &[ix], &[&user.wallet]).await.unwrap();
+
+    let user_base = token_account(&mut context, &user.base).await.unwrap();
+    assert_eq!(user_base.amount, 1_000);
+    let other_recipe = token_account(&mut context, &other.recipe).await.unwrap();
+    assert_eq!(other_recipe.amount, 0);
+    // recipe accounts stay open for other receipts
+    let user_recipe = token_account(&mut context, &user.recipe).await.unwrap();
+    assert_eq!(user_recipe.amount, 0);
+    assert_eq!(bank_state(&mut context, &bank).await.active_deposits, 0);
+}
+
+/// The asset of a new base mint in `bank`, as a `Bank` of that mint, vault and recipe mint
+async fn add_asset(context: &mut ProgramTestContext, bank: &Bank) -> Bank {
+    let base_mint = create_mint(context).await;
+    let owner = context.payer.pubkey();
+    let ix = instruction::add_asset(
+        &bank.program_id,
+        &bank.token_program,
+        &owner,
+        &bank.config,
+        &base_mint.pubkey(),
+    );
+    process(context, &[ix], &[]).await.unwrap();
+
+    let (asset, _) = find_asset_address(&bank.program_id, &bank.config, &base_mint.pubkey());
+    Bank {
+        program_id: bank.program_id,
+        token_program: bank.token_program,
+        base_mint,
+        config: bank.config,
+        vault: find_vault_address(&bank.program_id, &asset).0,
+        recipe_mint: find_recipe_mint_address(&bank.program_id, &asset).0,
+    }
+}
+
+fn deposit_asset_ix(asset: &Bank, user: &User, amount: u64) -> Instruction {
+    instruction::deposit_asset(
+        &asset.program_id,
+        &asset.token_program,
+        &user.wallet.pubkey(),
+        &asset.config,
+        &asset.base_mint.pubkey(),
+        &user.base,
+        &user.recipe,
+        amount,
+        DEPOSIT_TIME,
+    )
+}
+
+fn withdraw_asset_ix(asset: &Bank, user: &User) -> Instruction {
+    instruction::withdraw_asset(
+        &asset.program_id,
+        &asset.token_program,
+        &user.wallet.pubkey(),
+        &asset.config,
+        &asset.base_mint.pubkey(),
+        &user.base,
+        &user.recipe,
+    )
+}
+
+async fn asset_state(context: &mut ProgramTestContext, asset: &Bank) -> LunaAsset {
+    let (address, _) =
+        find_asset_address(&asset.program_id, &asset.config, &asset.base_mint.pubkey());
+    LunaAsset::unpack(&get_account(context, &address).await.data).unwrap()
+}
+
+#[tokio::test]
+async fn test_multi_asset_bank() {
+    let (mut context, bank) = setup().await;
+    let asset = add_asset(&mut context, &bank).await;
+    let state = asset_state(&mut context, &asset).await;
+    assert_eq!(state.bank, bank.config);
+    assert_eq!(state.base_token_account, asset.vault);
+    assert_eq!(state.recipe_token_mint, asset.recipe_mint);
+    let vault = token_account(&mut context, &asset.vault).await.unwrap();
+    let (authority, _) = find_bank_authority_address(&bank.program_id, &bank.config);
+    assert_eq!(vault.owner, authority);
+
+    // only the bank owner adds assets
+    let user = create_user(&mut context, &asset, 1_000).await;
+    let other_mint = create_mint(&mut context).await;
+    let ix = instruction::add_asset(
+        &bank.program_id,
+        &bank.token_program,
+        &user.wallet.pubkey(),
+        &bank.config,
+        &other_mint.pubkey(),
+    );
+    assert_error(
+        process(&mut context, &[ix], &[&user.wallet]).await,
+        LunabankError::BankOwnerMismatch,
+    );
+
+    // a user holds deposits in the bank and in its asset at the same time
+    let bank_user = User {
+        wallet: Keypair::from_bytes(&user.wallet.to_bytes()).unwrap(),
+        base: create_token_account(
+            &mut context,
+            &bank.base_mint.pubkey(),
+            &user.wallet.pubkey(),
+        )
+        .await,
+        recipe: create_token_account(&mut context, &bank.recipe_mint, &user.wallet.pubkey()).await,
+    };
+    let ix = spl_token::instruction::mint_to(
+        &spl_token::id(),
+        &bank.base_mint.pubkey(),
+        &bank_user.base,
+        &context.payer.pubkey(),
+        &[],
+        1_000,
+    )
+    .unwrap();
+    process(&mut context, &[ix], &[]).await.unwrap();
+    process(
+        &mut context,
+        &[
+            deposit_ix(&bank, &bank_user, 50),
+            deposit_asset_ix(&asset, &user, 100),
+        ],
+        &[&user.wallet],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        token_account(&mut context, &asset.vault)
+            .await
+            .unwrap()
+            .amount,
+        100
+    );
+    assert_eq!(
+        token_account(&mut context, &bank.vault)
+            .await
+            .unwrap()
+            .amount,
+        50
+    );
+    let user_recipe = token_account(&mut context, &user.recipe).await.unwrap();
+    assert_eq!(user_recipe.amount, 100);
+    assert!(user_recipe.is_frozen());
+    assert_eq!(bank_state(&mut context, &bank).await.active_deposits, 2);
+    assert_eq!(asset_state(&mut context, &asset).await.active_deposits, 1);
+
+    let (deposit, _) = find_deposit_address(
+        &bank.program_id,
+        &find_asset_address(&bank.program_id, &bank.config, &asset.base_mint.pubkey()).0,
+        &user.wallet.pubkey(),
+    );
+    let deposit_data =
+        LunaDeposit::unpack(&get_account(&mut context, &deposit).await.data).unwrap();
+    assert_eq!(deposit_data.bank, bank.config);
+    assert_eq!(deposit_data.base_token_mint, asset.base_mint.pubkey());
+
+    // the asset of one bank can't be used through another
+    let ix = instruction::init(
+        &bank.program_id,
+        &bank.token_program,
+        &context.payer.pubkey(),
+        &bank.base_mint.pubkey(),
+        1,
+    );
+    process(&mut context, &[ix], &[]).await.unwrap();
+    let (other_config, _) = find_bank_address(&bank.program_id, &bank.base_mint.pubkey(), 1);
+    let mut ix = withdraw_asset_ix(&asset, &user);
+    ix.accounts[1].pubkey = other_config;
+    assert_error(
+        process(&mut context, &[ix], &[&user.wallet]).await,
+        LunabankError::AssetBankMismatch,
+    );
+
+    // the bank stays open while asset deposits are
+    let owner = context.payer.pubkey();
+    process(
+        &mut context,
+        &[withdraw_ix(&bank, &bank_user)],
+        &[&user.wallet],
+    )
+    .await
+    .unwrap_err();
+    warp_clock(&mut context, DEPOSIT_TIME).await;
+    process(
+        &mut context,
+        &[withdraw_ix(&bank, &bank_user)],
+        &[&user.wallet],
+    )
+    .await
+    .unwrap();
+    assert_error(
+        process(&mut context, &[close_ix(&bank, &owner)], &[]).await,
+        LunabankError::DepositsOutstanding,
+    );
+
+    // the shutdown of the bank applies to its assets
+    let ix = instruction::shutdown(&bank.program_id, &owner, &bank.config);
+    process(&mut context, &[ix], &[]).await.unwrap();
+    let other = create_user(&mut context, &asset, 1_000).await;
+    assert_error(
+        process(
+            &mut context,
+            &[deposit_asset_ix(&asset, &other, 100)],
+            &[&other.wallet],
+        )
+        .await,
+        LunabankError::BankShutdown,
+    );
+
+    process(
+        &mut context,
+        &[withdraw_asset_ix(&asset, &user)],
+        &[&user.wallet],
+    )
+    .await
+    .unwrap();
+    let user_base = token_account(&mut context, &user.base).await.unwrap();
+    assert_eq!(user_base.amount, 1_000);
+    assert_eq!(
+        token_account(&mut context, &asset.vault)
+            .await
+            .unwrap()
+            .amount,
+        0
+    );
+    assert_eq!(bank_state(&mut context, &bank).await.active_deposits, 0);
+    assert_eq!(asset_state(&mut context, &asset).await.active_deposits, 0);
+}
+
+#[tokio::test]
+async fn test_close_bank_with_assets() {
+    let (mut context, bank) = setup().await;
+    let asset = add_asset(&mut context, &bank).await;
+    assert_eq!(bank_state(&mut context, &bank).await.asset_count, 1);
+    let owner = context.payer.pubkey();
+    let remove_asset_ix = instruction::remove_asset(
+        &bank.program_id,
+        &bank.token_program,
+        &owner,
+        &bank.config,
+        &asset.base_mint.pubkey(),
+    );
+
+    // the asset account would be left behind
+    assert_error(
+        process(&mut context, &[close_ix(&bank, &owner)], &[]).await,
+        LunabankError::AssetsOutstanding,
+    );
+
+    // only the bank owner removes assets
+    let user = create_user(&mut context, &asset, 1_000).await;
+    let ix = instruction::remove_asset(
+        &bank.program_id,
+        &bank.token_program,
+        &user.wallet.pubkey(),
+        &bank.config,
+        &asset.base_mint.pubkey(),
+    );
+    assert_error(
+        process(&mut context, &[ix], &[&user.wallet]).await,
+        LunabankError::BankOwnerMismatch,
+    );
+
+    // and not while deposits of the asset are open
+    process(
+        &mut context,
+        &[deposit_asset_ix(&asset, &user, 100)],
+        &[&user.wallet],
+    )
+    .await
+    .unwrap();
+    assert_error(
+        process(&mut context, std::slice::from_ref(&remove_asset_ix), &[]).await,
+        LunabankError::DepositsOutstanding,
+    );
+    warp_clock(&mut context, DEPOSIT_TIME).await;
+    process(
+        &mut context,
+        &[withdraw_asset_ix(&asset, &user)],
+        &[&user.wallet],
+    )
+    .await
+    .unwrap();
+
+    process(&mut context, &[remove_asset_ix], &[])
+        .await
+        .unwrap();
+    let (address, _) =
+        find_asset_address(&bank.program_id, &bank.config, &asset.base_mint.pubkey());
+    assert!(context
+        .banks_client
+        .get_account(address)
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(bank_state(&mut context, &bank).await.asset_count, 0);
+    let vault = token_account(&mut context, &asset.vault).await.unwrap();
+    assert_eq!(vault.owner, owner);
+    let recipe_mint =
+        spl_token::state::Mint::unpack(&get_account(&mut context, &asset.recipe_mint).await.data)
+            .unwrap();
+    assert_eq!(recipe_mint.mint_authority, Some(owner).into());
+    assert_eq!(recipe_mint.freeze_authority, Some(owner).into());
+
+    process(&mut context, &[close_ix(&bank, &owner)], &[])
+        .await
+        .unwrap();
+    assert!(context
+        .banks_client
+        .get_account(bank.config)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+/// A `FlashLoan` of `amount` to `user`, paid back by the test borrower out of `user.base`
+fn flash_loan_ix(bank: &Bank, user: &User, amount: u64) -> Instruction {
+    instruction::flash_loan(
+        &bank.program_id,
+        &bank.token_program,
+        &user.wallet.pubkey(),
+        &bank.config,
+        &bank.base_mint.pubkey(),
+        &user.base,
+        &borrower::id(),
+        &borrower::account_metas(
+            &bank.token_program,
+            &user.wallet.pubkey(),
+            &user.base,
+            &bank.base_mint.pubkey(),
+            &bank.vault,
+        ),
+        amount,
+    )
+}
+
+#[tokio::test]
+async fn test_flash_loan() {
+    let (mut context, bank) = setup().await;
+    let user = create_user(&mut context, &bank, 1_000).await;
+    process(
+        &mut context,
+        &[deposit_ix(&bank, &user, 1_000)],
+        &[&user.wallet],
+    )
+    .await
+    .unwrap();
+
+    // the fee of 9 bps is rounded up to 1
+    let borrower = create_user(&mut context, &bank, 10).await;
+    process(
+        &mut context,
+        &[flash_loan_ix(&bank, &borrower, 1_000)],
+        &[&borrower.wallet],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        token_account(&mut context, &bank.vault)
+            .await
+            .unwrap()
+            .amount,
+        1_001
+    );
+    assert_eq!(
+        token_account(&mut context, &borrower.base)
+            .await
+            .unwrap()
+            .amount,
+        9
+    );
+    assert!(!bank_state(&mut context, &bank).await.is_locked);
+
+    // paying back the amount without the fee
+    let short = create_user(&mut context, &bank, 0).await;
+    assert_error(
+        process(
+            &mut context,
+            &[flash_loan_ix(&bank, &short, 1_000)],
+            &[&short.wallet],
+        )
+        .await,
+        LunabankError::FlashLoanNotRepaid,
+    );
+    assert_eq!(
+        token_account(&mut context, &short.base)
+            .await
+            .unwrap()
+            .amount,
+        0
+    );
+
+    // more than the vault holds
+    process(
+        &mut context,
+        &[flash_loan_ix(&bank, &borrower, 1_002)],
+        &[&borrower.wallet],
+    )
+    .await
+    .unwrap_err();
+
+    let owner = context.payer.pubkey();
+    let ix = instruction::shutdown(&bank.program_id, &owner, &bank.config);
+    process(&mut context, &[ix], &[]).await.unwrap();
+    assert_error(
+        process(
+            &mut context,
+            &[flash_loan_ix(&bank, &borrower, 100)],
+            &[&borrower.wallet],
+        )
+        .await,
+        LunabankError::BankShutdown,
+    );
+
+    // the fees stay in the vault, the owner gets them with it
+    process(&mut context, &[withdraw_ix(&bank, &user)], &[&user.wallet])
+        .await
+        .unwrap();
+    process(&mut context, &[close_ix(&bank, &owner)], &[])
+        .await
+        .unwrap();
+    let vault = token_account(&mut context, &bank.vault).await.unwrap();
+    assert_eq!(vault.owner, owner);
+    assert_eq!(vault.amount, 1);
+}
+
+#[tokio::test]
+async fn test_locked_bank() {
+    let (mut context, bank) = setup().await;
+    let user = create_user(&mut context, &bank, 1_000).await;
+    process(
+        &mut context,
+        &[deposit_ix(&bank, &user, 100)],
+        &[&user.wallet],
+    )
+    .await
+    .unwrap();
+
+    // the state a bank is in while the borrower program runs, tests/reentrancy.rs has a
+    // borrower get there by calling back into the bank
+    let mut account = get_account(&mut context, &bank.config).await;
+    account.data[Lunabank::IS_LOCKED_OFFSET] = 1;
+    context.set_account(&bank.config, &account.into());
+
+    let owner = context.payer.pubkey();
+    let other = create_user(&mut context, &bank, 1_000).await;
+    for (ix, signer) in [
+        (deposit_ix(&bank, &other, 100), &other.wallet),
+        (withdraw_ix(&bank, &user), &user.wallet),
+        (flash_loan_ix(&bank, &other, 10), &other.wallet),
+    ] {
+        assert_error(
+            process(&mut context, &[ix], &[signer]).await,
+            LunabankError::BankLocked,
+        );
+    }
+    for ix in [
+        instruction::shutdown(&bank.program_id, &owner, &bank.config),
+        close_ix(&bank, &owner),
+    ] {
+        assert_error(
+            process(&mut context, &[ix], &[]).await,
+            LunabankError::BankLocked,
+        );
+    }
+}
@@ -0,0 +1,240 @@
+//! A borrower calling back into the bank during its flash loan. The runtime refuses to invoke
+//! a program that is already running further up, before the program runs, and program-test
+//! gives up on the bank when it does, so here the programs run natively on `AccountInfo`s with
+//! syscall stubs that route invocations without that check. The lock of the bank is then all
+//! that stops the borrower.
+mod common;
+
+use common::borrower;
+use lunabank::{
+    error::LunabankError,
+    instruction,
+    processor::Processor,
+    state::{find_bank_authority_address, find_vault_address, Key, Lunabank, RecipeMode},
+};
+use solana_program::{
+    account_info::AccountInfo,
+    bpf_loader,
+    entrypoint::{ProcessInstruction, ProgramResult},
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    program_option::COption,
+    program_pack::Pack,
+    program_stubs::{set_syscall_stubs, SyscallStubs},
+    pubkey::Pubkey,
+};
+use spl_token::state::{Account as TokenAccount, AccountState, Mint};
+
+/// Runs invoked instructions with the native processor of their program. Signer privileges
+/// are taken from the instruction as the runtime would grant them, the seeds aren't checked.
+struct Router {
+    programs: Vec<(Pubkey, ProcessInstruction)>,
+}
+
+impl SyscallStubs for Router {
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        _signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let (_, process) = self
+            .programs
+            .iter()
+            .find(|(program_id, _)| *program_id == instruction.program_id)
+            .ok_or(ProgramError::IncorrectProgramId)?;
+        process(
+            &instruction.program_id,
+            &instruction_accounts(account_infos, &instruction.accounts),
+            &instruction.data,
+        )
+    }
+}
+
+/// The accounts of `metas` out of `account_infos`, sharing their lamports and data. An
+/// account listed more than once has the privileges of all its entries, as in the runtime.
+fn instruction_accounts<'a>(
+    account_infos: &[AccountInfo<'a>],
+    metas: &[AccountMeta],
+) -> Vec<AccountInfo<'a>> {
+    metas
+        .iter()
+        .map(|meta| {
+            let mut info = account_infos
+                .iter()
+                .find(|info| *info.key == meta.pubkey)
+                .unwrap()
+                .clone();
+            let entries = metas.iter().filter(|other| other.pubkey == meta.pubkey);
+            info.is_signer = entries.clone().any(|other| other.is_signer);
+            info.is_writable = entries.clone().any(|other| other.is_writable);
+            info
+        })
+        .collect()
+}
+
+struct TestAccount {
+    key: Pubkey,
+    lamports: u64,
+    data: Vec<u8>,
+    owner: Pubkey,
+    executable: bool,
+}
+
+impl TestAccount {
+    fn new(key: Pubkey, data: Vec<u8>, owner: Pubkey) -> Self {
+        TestAccount {
+            key,
+            lamports: 1_000_000_000,
+            data,
+            owner,
+            executable: false,
+        }
+    }
+
+    fn program(key: Pubkey) -> Self {
+        TestAccount {
+            executable: true,
+            ..TestAccount::new(key, vec![], bpf_loader::id())
+        }
+    }
+
+    fn info(&mut self) -> AccountInfo<'_> {
+        AccountInfo::new(
+            &self.key,
+            false,
+            false,
+            &mut self.lamports,
+            &mut self.data,
+            &self.owner,
+            self.executable,
+            0,
+        )
+    }
+}
+
+fn pack<T: Pack>(state: T) -> Vec<u8> {
+    let mut data = vec![0; T::LEN];
+    T::pack(state, &mut data).unwrap();
+    data
+}
+
+fn token_account(mint: &Pubkey, owner: &Pubkey, amount: u64) -> Vec<u8> {
+    pack(TokenAccount {
+        mint: *mint,
+        owner: *owner,
+        amount,
+        delegate: COption::None,
+        state: AccountState::Initialized,
+        is_native: COption::None,
+        delegated_amount: 0,
+        close_authority: COption::None,
+    })
+}
+
+fn token_amount(info: &AccountInfo) -> u64 {
+    TokenAccount::unpack(&info.data.borrow()).unwrap().amount
+}
+
+#[test]
+fn test_flash_loan_reentry() {
+    let program_id = Pubkey::new_unique();
+    set_syscall_stubs(Box::new(Router {
+        programs: vec![
+            (program_id, Processor::process),
+            (borrower::id(), borrower::process),
+            (spl_token::id(), spl_token::processor::Processor::process),
+        ],
+    }));
+
+    let config = Pubkey::new_unique();
+    let base_mint = Pubkey::new_unique();
+    let vault = find_vault_address(&program_id, &config).0;
+    let (authority, pda_bump_seed) = find_bank_authority_address(&program_id, &config);
+    let borrower_wallet = Pubkey::new_unique();
+    let borrower_base = Pubkey::new_unique();
+    let bank = Lunabank {
+        key: Key::LunabankV2,
+        owner: Pubkey::new_unique(),
+        base_token_mint: base_mint,
+        recipe_token_mint: Pubkey::new_unique(),
+        base_token_account: vault,
+        coef: 1,
+        is_shutdown: false,
+        active_deposits: 1,
+        bank_id: 0,
+        pda_bump_seed,
+        recipe_mode: RecipeMode::Frozen,
+        token_program: spl_token::id(),
+        is_locked: false,
+        asset_count: 0,
+        reserved: [0; 22],
+    };
+    let mint = Mint {
+        mint_authority: COption::None,
+        supply: 1_010,
+        decimals: 0,
+        is_initialized: true,
+        freeze_authority: COption::None,
+    };
+    let mut accounts = vec![
+        TestAccount::new(config, pack(bank), program_id),
+        TestAccount::new(base_mint, pack(mint), spl_token::id()),
+        TestAccount::new(
+            vault,
+            token_account(&base_mint, &authority, 1_000),
+            spl_token::id(),
+        ),
+        TestAccount::new(
+            borrower_base,
+            token_account(&base_mint, &borrower_wallet, 10),
+            spl_token::id(),
+        ),
+        TestAccount::new(borrower_wallet, vec![], Pubkey::default()),
+        TestAccount::new(authority, vec![], Pubkey::default()),
+        TestAccount::program(program_id),
+        TestAccount::program(borrower::id()),
+        TestAccount::program(spl_token::id()),
+    ];
+    let infos: Vec<AccountInfo> = accounts.iter_mut().map(TestAccount::info).collect();
+    let flash_loan_ix = || {
+        instruction::flash_loan(
+            &program_id,
+            &spl_token::id(),
+            &borrower_wallet,
+            &config,
+            &base_mint,
+            &borrower_base,
+            &borrower::id(),
+            &borrower::account_metas(
+                &spl_token::id(),
+                &borrower_wallet,
+                &borrower_base,
+                &base_mint,
+                &vault,
+            ),
+            10,
+        )
+    };
+    let process = |ix: &Instruction| {
+        Processor::process(
+            &ix.program_id,
+            &instruction_accounts(&infos, &ix.accounts),
+            &ix.data,
+        )
+    };
+
+    // the routing lends and takes the loan back like the runtime
+    process(&flash_loan_ix()).unwrap();
+    assert_eq!(token_amount(&infos[2]), 1_001);
+    assert_eq!(token_amount(&infos[3]), 9);
+    assert!(!Lunabank::unpack(&infos[0].data.borrow()).unwrap().is_locked);
+
+    // the borrower asks for another loan before paying this one back
+    let mut ix = flash_loan_ix();
+    ix.accounts.extend(borrower::reentry_account_metas(
+        &program_id,
+        flash_loan_ix().accounts,
+    ));
+    assert_eq!(process(&ix), Err(LunabankError::BankLocked.into()));
+}
@@ -40,19 +40,13 @@ program
     .option('-k, --keypair <string>', 'rewrite key to create, default: alice', './keys/alice.json')
     .requiredOption('-p, --program-id <string>', 'programid')
     .requiredOption('-b, --base-token-mint <string>', 'base token mint')
-    .requiredOption('-r, --recipe-token-mint <string>', 'recipe token mint')
-    .option(
-        '-g, --gen-new',
-        'Optional: Generate keys to create new contract',
-    )
+    .option('-i, --bank-id <number>', 'bank id, unique per base token mint', '0')
     // eslint-disable-next-line @typescript-eslint/no-unused-vars
     .action(async (directory, cmd) => {
         //console.log(">>>>>>>>!!!!!!", directory, cmd)
-        const {keypair, programId, baseTokenMint, recipeTokenMint, genNew} = cmd.opts();
+        const {keypair, programId, baseTokenMint, bankId} = cmd.opts();
         console.log("keypair: ", keypair);
-        console.log("genNew: ", genNew);
         console.log("baseTokenMint: ", baseTokenMint);
-        console.log("RecipeTokenMint: ", recipeTokenMint);
         console.log("programid: ", programId);
 
         const callerKp = getKeypairByPath(keypair);
@@ -60,78 +54,61 @@ program
         console.log(callerKp.publicKey.toBase58());
         const LunabankProgramPubkey = new PublicKey(programId);
         const baseTokenMintPubkey = new PublicKey(baseTokenMint);
-        const recipeTokenMintPubkey = new PublicKey(recipeTokenMint);
         console.log("LunabankProgramId", LunabankProgramPubkey.toBase58());
 
         const connection = new Connection("http://localhost:8899", "confirmed");
 
-        const configKeypair = new Keypair();
-        const depositaryTokenAccountKeypair = new Keypair();
-
-        const createDepositaryTokenAccountIx = SystemProgram.createAccount({
-            programId: TOKEN_PROGRAM_ID,
-            space: AccountLayout.span,
-            lamports: await connection.getMinimumBalanceForRentExemption(
-                AccountLayout.span
-            ),
-            fromPubkey: callerKp.publicKey,
-            newAccountPubkey: depositaryTokenAccountKeypair.publicKey,
-        });
-        const initDepositaryAccountIx = Token.createInitAccountInstruction(
-            TOKEN_PROGRAM_ID,
-            baseTokenMintPubkey,
-            depositaryTokenAccountKeypair.publicKey,
-            callerKp.publicKey
+        const bankIdBytes = new BN(bankId, 10).toArrayLike(Buffer, "le", 8);
+        const configPDA = await PublicKey.findProgramAddress(
+            [Buffer.from("lunabank"), baseTokenMintPubkey.toBuffer(), bankIdBytes],
+            LunabankProgramPubkey
+        );
+        const vaultPDA = await PublicKey.findProgramAddress(
+            [configPDA[0].toBuffer(), Buffer.from("vault")],
+            LunabankProgramPubkey
+        );
+        const recipePDA = await PublicKey.findProgramAddress(
+            [configPDA[0].toBuffer(), Buffer.from("recipe")],
+            LunabankProgramPubkey
+        );
+        const ownerPDA = await PublicKey.findProgramAddress(
+            [configPDA[0].toBuffer(), Buffer.from("lunaowner")],
+            LunabankProgramPubkey
+        );
+        const registryPDA = await PublicKey.findProgramAddress(
+            [Buffer.from("registry"), baseTokenMintPubkey.toBuffer(), bankIdBytes],
+            LunabankProgramPubkey
         );
-
-        // const transferXTokensToTempAccIx = Token.createTransferInstruction(
-        //     TOKEN_PROGRAM_ID,
-        //     aliceXTokenAccountPubkey,
-        //     tempXTokenAccountKeypair.publicKey,
-        //     aliceKeypair.publicKey,
-        //     [],
-        //     terms.bobExpectedAmount
-        // );
-        /////
-        const createLunabankConfigAccountIx = SystemProgram.createAccount({
-            space: LUNABANK_ACCOUNT_DATA_LAYOUT.span,
-            lamports: await connection.getMinimumBalanceForRentExemption(
-                LUNABANK_ACCOUNT_DATA_LAYOUT.span
-            ),
-            fromPubkey: callerKp.publicKey,
-            newAccountPubkey: configKeypair.publicKey,
-            programId: LunabankProgramPubkey,
-        });
 
         const initConfigBankAccountIx = new TransactionInstruction({
             programId: LunabankProgramPubkey,
             keys: [
-                { pubkey: callerKp.publicKey, isSigner: true, isWritable: false },
-                { pubkey: configKeypair.publicKey, isSigner: false, isWritable: true },
+                { pubkey: callerKp.publicKey, isSigner: true, isWritable: true },
+                { pubkey: configPDA[0], isSigner: false, isWritable: true },
                 { pubkey: baseTokenMintPubkey, isSigner: false, isWritable: false },
-                { pubkey: depositaryTokenAccountKeypair.publicKey, isSigner: false, isWritable: false },
-                { pubkey: recipeTokenMintPubkey, isSigner: false, isWritable: true },
+                { pubkey: vaultPDA[0], isSigner: false, isWritable: true },
+                { pubkey: recipePDA[0], isSigner: false, isWritable: true },
                 { pubkey: SYSVAR_RENT_PUBKEY, isSigner: false, isWritable: false },
                 { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+                { pubkey: registryPDA[0], isSigner: false, isWritable: true },
+                { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+                { pubkey: ownerPDA[0], isSigner: false, isWritable: false },
             ],
-            data: Buffer.from(
-                Uint8Array.of(0)
-            ),
+            data: Buffer.concat([
+                Buffer.from(Uint8Array.of(0)),
+                bankIdBytes,
+            ]),
         });
 
         const tx = new Transaction().add(
-            createDepositaryTokenAccountIx,
-            initDepositaryAccountIx,
-            createLunabankConfigAccountIx,
             initConfigBankAccountIx,
         );
-        console.log("basetoken acc = ", depositaryTokenAccountKeypair.publicKey.toBase58());
-        console.log("Sending init transaction..., account data addr = ", configKeypair.publicKey.toBase58());
+        console.log("basetoken acc = ", vaultPDA[0].toBase58());
+        console.log("recipe mint = ", recipePDA[0].toBase58());
+        console.log("Sending init transaction..., account data addr = ", configPDA[0].toBase58());
         await connection.sendTransaction(
             tx,
-//          [callerKp,],
-//            [callerKp, depositaryTokenAccountKeypair],
-            [callerKp, configKeypair, depositaryTokenAccountKeypair],
+            [callerKp],
             { skipPreflight: false, preflightCommitment: "confirmed" }
         );
 
@@ -173,10 +150,14 @@ const dumpUserConfig = async (
     const startTimestamp = new BN(decodedLunaDepositState.startTimestamp, 10, "le").toNumber();
     const endTimestamp = new BN(decodedLunaDepositState.endTimestamp, 10, "le").toNumber();
 
+    console.log("key = ", decodedLunaDepositState.key);
     console.log("owner = ", owner);
     console.log("amount  = ", amount);
     console.log("startTimestamp  = ", new Date(startTimestamp * 1000));
     console.log("endTimestamp  = ", new Date(endTimestamp * 1000));
+    console.log("bumpSeed  = ", decodedLunaDepositState.bumpSeed);
+    console.log("bank  = ", new PublicKey(decodedLunaDepositState.bank).toBase58());
+    console.log("baseTokenMint  = ", new PublicKey(decodedLunaDepositState.baseTokenMint).toBase58());
 }
 
 const dumpConfig = async (
@@ -226,12 +207,20 @@ const printConfig =  (
         encodedLunabankState
     ) as LunabankLayout;
     console.log("raw = ", decodedLunabankState);
-    console.log("config.init =  ", !!decodedLunabankState.isInitialized,);
+    console.log("config.key ", decodedLunabankState.key);
     console.log("config.owner ", new PublicKey(decodedLunabankState.owner).toBase58());
     console.log("config.baseTokenMint ", new PublicKey(decodedLunabankState.baseTokenMint).toBase58());
     console.log("config.recipeTokenMint ", new PublicKey(decodedLunabankState.recipeTokenMint).toBase58());
     console.log("config.baseTokenAccount ", new PublicKey(decodedLunabankState.baseTokenAccount).toBase58());
     console.log("config.coef ", new BN(decodedLunabankState.coef, 10, "le").toString());
+    console.log("config.isShutdown ", !!decodedLunabankState.isShutdown);
+    console.log("config.activeDeposits ", new BN(decodedLunabankState.activeDeposits, 10, "le").toString());
+    console.log("config.bankId ", new BN(decodedLunabankState.bankId, 10, "le").toString());
+    console.log("config.pdaBumpSeed ", decodedLunabankState.pdaBumpSeed);
+    console.log("config.recipeMode ", decodedLunabankState.recipeMode);
+    console.log("config.tokenProgram ", new PublicKey(decodedLunabankState.tokenProgram).toBase58());
+    console.log("config.isLocked ", !!decodedLunabankState.isLocked);
+    console.log("config.assetCount ", new BN(decodedLunabankState.assetCount, 10, "le").toString());
 };
 
 
@@ -377,17 +366,26 @@ program
             LunabankProgramPubkey
         );
         console.log("PDA owner = ", PDA[0].toBase58());
+        const registryPDA = await PublicKey.findProgramAddress(
+            [
+                Buffer.from("registry"),
+                new PublicKey(decodedLunabankState.baseTokenMint).toBuffer(),
+                Buffer.from(decodedLunabankState.bankId),
+            ],
+            LunabankProgramPubkey
+        );
 
 
         const closeRiverIx = new TransactionInstruction({
             programId: LunabankProgramPubkey,
             keys: [
-                { pubkey: callerKp.publicKey, isSigner: true, isWritable: false },
+                { pubkey: callerKp.publicKey, isSigner: true, isWritable: true },
                 { pubkey: ConfigProgramPubkey, isSigner: false, isWritable: true },
                 { pubkey: baseTokenAccount, isSigner: false, isWritable: true },
                 { pubkey: recipeTokenMint, isSigner: false, isWritable: true },
                 { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
                 { pubkey: PDA[0], isSigner: false, isWritable: false },
+                { pubkey: registryPDA[0], isSigner: false, isWritable: true },
             ],
             data: Buffer.from(
                 Uint8Array.of(1)
@@ -74,35 +74,61 @@ const uint64 = (property = "uint64") => {
 };
 
 export const LUNABANK_ACCOUNT_DATA_LAYOUT = BufferLayout.struct([
-  BufferLayout.u8("isInitialized"),
+  BufferLayout.u8("key"),
   publicKey("owner"),
   publicKey("baseTokenMint"),
   publicKey("recipeTokenMint"),
   publicKey("baseTokenAccount"),
   uint64("coef"),
+  BufferLayout.u8("isShutdown"),
+  uint64("activeDeposits"),
+  uint64("bankId"),
+  BufferLayout.u8("pdaBumpSeed"),
+  BufferLayout.u8("recipeMode"),
+  publicKey("tokenProgram"),
+  BufferLayout.u8("isLocked"),
+  uint64("assetCount"),
+  BufferLayout.blob(22, "reserved"),
 ]);
 
 export interface LunabankLayout {
-  isInitialized: number;
+  key: number;
   owner: Uint8Array;
   baseTokenMint: Uint8Array;
   recipeTokenMint: Uint8Array;
   baseTokenAccount: Uint8Array;
   coef: Uint8Array;
+  isShutdown: number;
+  activeDeposits: Uint8Array;
+  bankId: Uint8Array;
+  pdaBumpSeed: number;
+  recipeMode: number;
+  tokenProgram: Uint8Array;
+  isLocked: number;
+  assetCount: Uint8Array;
+  reserved: Uint8Array;
 }
 
 export const LUNADEPOSIT_ACCOUNT_DATA_LAYOUT = BufferLayout.struct([
-  BufferLayout.u8("isInitialized"),
+  BufferLayout.u8("key"),
   publicKey("owner"),
   uint64("amount"),
   uint64("startTimestamp"),
   uint64("endTimestamp"),
+  BufferLayout.u8("bumpSeed"),
+  publicKey("bank"),
+  publicKey("baseTokenMint"),
+  BufferLayout.blob(32, "reserved"),
 ]);
 
 export interface LunaDepositLayout {
-  isInitialized: number;
+  key: number;
   owner: Uint8Array;
   amount: Uint8Array;
   startTimestamp: Uint8Array;
   endTimestamp: Uint8Array;
+  bumpSeed: number;
+  bank: Uint8Array;
+  baseTokenMint: Uint8Array;
+  reserved: Uint8Array;
 }